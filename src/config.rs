@@ -1,12 +1,73 @@
 use std::net::SocketAddr;
 use std::time::Duration;
 
+use crate::estimator::EstimatorKind;
+use crate::util::{is_reachable_addr, generate_node_id};
+
 pub struct Config {
     pub ping_interval: Duration, // seconds
     pub num_members_to_ping: u8,
+    /// How long we wait for a member to answer a ping before asking others
+    /// to probe it for us, and then for them to report back.
+    pub probe_timeout: Duration,
+    /// How many members we ask to probe a member that didn't answer us.
+    pub num_indirect_probes: u8,
+    /// How high our local health score can go, see `LocalHealth`. 0 turns
+    /// local health awareness off.
+    pub max_local_health_score: u32,
+    /// How late a tick, or how long handling a single event, can be before
+    /// it counts against our local health.
+    pub lag_tolerance: Duration,
     pub window_size: usize,
+    /// Cap on the serialized size of the gossip we send, in bytes. The
+    /// default keeps datagrams within an Ethernet MTU, IP and UDP headers
+    /// included. Members that don't fit are left for later gossip.
+    pub max_gossip_size: usize,
     pub addr: SocketAddr,
+    /// The address peers can reach us at, if not `addr`, say because we are
+    /// behind NAT or a port mapping.
+    pub advertise_addr: Option<SocketAddr>,
+    /// Ignore gossip whose sender doesn't claim the address the gossip came
+    /// from. Only useful when no peer is behind NAT.
+    pub verify_sender_addr: bool,
+    /// Identifies this node across restarts and address changes. If not
+    /// given, the advertised address is used, see `Config::node_id()`.
+    pub node_id: Option<String>,
+    /// Go by a random node ID, new with every start, when `node_id` isn't
+    /// given.
+    pub random_node_id: bool,
+    /// Upper bound, in seconds, of a random delay before every periodic
+    /// ping-out. This spreads out the pings of nodes started together, and
+    /// makes for a slow node when testing.
     pub ticker_delay: Option<u8>,
+    pub estimator: EstimatorKind,
+    /// Floor on the standard deviation of inter-arrival times, so that very
+    /// regular pings do not make the detector overly sensitive.
+    pub min_std_deviation: Duration,
+    /// Silence we tolerate on top of the usual inter-arrival time before
+    /// suspicion starts building up.
+    pub acceptable_heartbeat_pause: Duration,
+    /// Assumed mean inter-arrival time for members we have just heard of,
+    /// before we have any samples from them. Defaults to the ping interval.
+    pub bootstrap_mean: Option<Duration>,
+    /// Assumed standard deviation to go with `bootstrap_mean`. Defaults to a
+    /// quarter of the mean.
+    pub bootstrap_std_deviation: Option<Duration>,
+    /// The phi at which a member becomes a suspect.
+    pub suspicion_threshold: f64,
+    /// How long we go by the phi another member gossiped for a member, see
+    /// `ClusterSuspicion`.
+    pub opinion_max_age: Duration,
+    /// How long a member stays a suspect before we declare it dead.
+    pub suspect_timeout: Duration,
+    /// How long a dead member is kept around before it is removed.
+    pub reap_timeout: Duration,
+    /// How many events a subscriber can fall behind by before it starts
+    /// missing some, see `EventBus`.
+    pub event_capacity: usize,
+    /// How long we remember reaped members, to ignore stale gossip about
+    /// them.
+    pub tombstone_grace_period: Duration,
 }
 
 impl Config {
@@ -14,12 +75,40 @@ impl Config {
         Config {
             ping_interval: Duration::from_millis(1000),
             num_members_to_ping: 3,
+            probe_timeout: Duration::from_millis(500),
+            num_indirect_probes: 3,
+            max_local_health_score: 8,
+            lag_tolerance: Duration::from_millis(200),
             addr: "0.0.0.0:12345".parse::<SocketAddr>().unwrap(),
+            advertise_addr: None,
+            verify_sender_addr: false,
             window_size: 10usize,
+            max_gossip_size: 1400,
+            node_id: None,
+            random_node_id: false,
             ticker_delay: None,
+            estimator: EstimatorKind::Normal,
+            min_std_deviation: Duration::from_millis(100),
+            acceptable_heartbeat_pause: Duration::from_millis(0),
+            bootstrap_mean: None,
+            bootstrap_std_deviation: None,
+            suspicion_threshold: 8f64,
+            opinion_max_age: Duration::from_millis(10000),
+            suspect_timeout: Duration::from_millis(5000),
+            reap_timeout: Duration::from_millis(30000),
+            tombstone_grace_period: Duration::from_millis(120000),
+            event_capacity: 4096,
         }
     }
 
+    /// The (mean, standard deviation) of inter-arrival times we assume for
+    /// newly discovered members.
+    pub fn bootstrap_prior(&self) -> (Duration, Duration) {
+        let mean = self.bootstrap_mean.unwrap_or(self.ping_interval);
+        let stddev = self.bootstrap_std_deviation.unwrap_or(mean / 4);
+        (mean, stddev)
+    }
+
     pub fn set_ping_interval(&mut self, interval: Duration) -> &mut Config {
         self.ping_interval = interval;
         self
@@ -30,18 +119,145 @@ impl Config {
         self
     }
 
+    pub fn set_probe_timeout(&mut self, timeout: Duration) -> &mut Config {
+        self.probe_timeout = timeout;
+        self
+    }
+
+    pub fn set_num_indirect_probes(&mut self, n: u8) -> &mut Config {
+        self.num_indirect_probes = n;
+        self
+    }
+
+    pub fn set_max_local_health_score(&mut self, max_score: u32) -> &mut Config {
+        self.max_local_health_score = max_score;
+        self
+    }
+
+    pub fn set_lag_tolerance(&mut self, tolerance: Duration) -> &mut Config {
+        self.lag_tolerance = tolerance;
+        self
+    }
+
     pub fn set_addr(&mut self, addr: SocketAddr) -> &mut Config {
         self.addr = addr;
         self
     }
 
+    pub fn set_advertise_addr(&mut self, addr: SocketAddr) -> &mut Config {
+        self.advertise_addr = Some(addr);
+        self
+    }
+
+    /// The address to tell peers to reach us at. Unless told otherwise, that
+    /// is the address we listen on, provided it is one peers can make sense
+    /// of, which `0.0.0.0` or port 0 aren't.
+    pub fn advertised_addr(&self) -> Option<SocketAddr> {
+        self.advertise_addr.or_else(|| {
+            if is_reachable_addr(self.addr) {
+                Some(self.addr)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// The ID to go by: `node_id` if given, and otherwise the advertised
+    /// address, so that a node restarting in place is still the same member.
+    /// A random ID is generated if `random_node_id` asks for it, or if there
+    /// is no advertised address to go by.
+    pub fn node_id(&self) -> String {
+        if let Some(ref node_id) = self.node_id {
+            return node_id.clone();
+        }
+        match self.advertised_addr() {
+            Some(addr) if !self.random_node_id => addr.to_string(),
+            Some(_) => generate_node_id(),
+            None => {
+                warn!("no address to go by across restarts, using a random node ID");
+                generate_node_id()
+            }
+        }
+    }
+
+    pub fn set_verify_sender_addr(&mut self, verify: bool) -> &mut Config {
+        self.verify_sender_addr = verify;
+        self
+    }
+
+    pub fn set_node_id(&mut self, node_id: String) -> &mut Config {
+        self.node_id = Some(node_id);
+        self
+    }
+
+    pub fn set_random_node_id(&mut self, random: bool) -> &mut Config {
+        self.random_node_id = random;
+        self
+    }
+
     pub fn set_window_size(&mut self, sz: usize) -> &mut Config {
         self.window_size = sz;
         self
     }
 
+    pub fn set_max_gossip_size(&mut self, size: usize) -> &mut Config {
+        self.max_gossip_size = size;
+        self
+    }
+
     pub fn set_ticker_delay(&mut self, level: u8) -> &mut Config {
         self.ticker_delay = Some(level);
         self
     }
+
+    pub fn set_estimator(&mut self, estimator: EstimatorKind) -> &mut Config {
+        self.estimator = estimator;
+        self
+    }
+
+    pub fn set_min_std_deviation(&mut self, min_std: Duration) -> &mut Config {
+        self.min_std_deviation = min_std;
+        self
+    }
+
+    pub fn set_acceptable_heartbeat_pause(&mut self, pause: Duration) -> &mut Config {
+        self.acceptable_heartbeat_pause = pause;
+        self
+    }
+
+    pub fn set_bootstrap_prior(&mut self, mean: Duration, stddev: Duration) -> &mut Config {
+        self.bootstrap_mean = Some(mean);
+        self.bootstrap_std_deviation = Some(stddev);
+        self
+    }
+
+    pub fn set_suspicion_threshold(&mut self, threshold: f64) -> &mut Config {
+        self.suspicion_threshold = threshold;
+        self
+    }
+
+    pub fn set_opinion_max_age(&mut self, max_age: Duration) -> &mut Config {
+        self.opinion_max_age = max_age;
+        self
+    }
+
+    pub fn set_suspect_timeout(&mut self, timeout: Duration) -> &mut Config {
+        self.suspect_timeout = timeout;
+        self
+    }
+
+    pub fn set_reap_timeout(&mut self, timeout: Duration) -> &mut Config {
+        self.reap_timeout = timeout;
+        self
+    }
+
+    pub fn set_event_capacity(&mut self, capacity: usize) -> &mut Config {
+        self.event_capacity = capacity;
+        self
+    }
+
+    pub fn set_tombstone_grace_period(&mut self, period: Duration) -> &mut Config {
+        self.tombstone_grace_period = period;
+        self
+    }
 }
@@ -0,0 +1,579 @@
+use std::collections::VecDeque;
+use std::f64::consts::{LOG10_E, PI, SQRT_2};
+use std::fmt::Debug;
+
+use statrs::function::erf::erfc;
+
+/// Something that learns the distribution of inter-arrival times of pings
+/// from a member, and can tell how suspicious a given silence is under that
+/// distribution. All times are in seconds.
+pub trait InterArrivalEstimator: Debug + Send {
+    /// Add an observed inter-arrival time.
+    fn update(&mut self, sample: f64);
+
+    /// Prime the estimator with a prior belief that inter-arrival times have
+    /// the given mean and standard deviation, for use until enough real
+    /// samples have come in.
+    fn seed(&mut self, mean: f64, stddev: f64);
+
+    /// The suspicion level given that `elapsed` seconds have passed since the
+    /// last arrival, or `None` if we don't know enough yet to tell. The
+    /// standard deviation of the estimated distribution is taken to be at
+    /// least `min_stddev` seconds, for estimators where that makes sense.
+    fn phi(&self, elapsed: f64, min_stddev: f64) -> Option<f64>;
+
+    /// The mean inter-arrival time under the estimated distribution.
+    fn mean(&self) -> Option<f64>;
+
+    /// The variance of inter-arrival times under the estimated distribution.
+    fn variance(&self) -> Option<f64>;
+
+    fn box_clone(&self) -> Box<dyn InterArrivalEstimator>;
+}
+
+impl Clone for Box<dyn InterArrivalEstimator> {
+    fn clone(&self) -> Box<dyn InterArrivalEstimator> {
+        self.box_clone()
+    }
+}
+
+/// The model of inter-arrival times to use for all members of the cluster.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EstimatorKind {
+    /// A normal distribution fitted over a sliding window of samples, as in
+    /// the original paper.
+    Normal,
+    /// An exponential distribution fitted over a sliding window of samples,
+    /// which is what Cassandra does. Its phi grows linearly with the silence.
+    Exponential,
+    /// A log-normal distribution fitted over a sliding window of samples. This
+    /// is a better fit for heavy-tailed inter-arrival times.
+    LogNormal,
+    /// A normal distribution tracked using exponentially weighted moving
+    /// averages with the given smoothing factor.
+    Ewma(f64),
+}
+
+impl EstimatorKind {
+    pub fn from_name(name: &str) -> Option<EstimatorKind> {
+        match name {
+            "normal" => Some(EstimatorKind::Normal),
+            "exponential" => Some(EstimatorKind::Exponential),
+            "lognormal" => Some(EstimatorKind::LogNormal),
+            "ewma" => Some(EstimatorKind::Ewma(0.9f64)),
+            _ => None,
+        }
+    }
+
+    pub fn build(&self, window_size: usize) -> Box<dyn InterArrivalEstimator> {
+        match *self {
+            EstimatorKind::Normal => Box::new(NormalEstimator::of_size(window_size)),
+            EstimatorKind::Exponential => Box::new(ExponentialEstimator::of_size(window_size)),
+            EstimatorKind::LogNormal => Box::new(LogNormalEstimator::of_size(window_size)),
+            EstimatorKind::Ewma(alpha) => Box::new(EwmaEstimator::new(alpha)),
+        }
+    }
+}
+
+
+/// A ring buffer of the last `size` samples.
+#[derive(Clone, Debug)]
+pub struct SampleWindow {
+    samples: VecDeque<f64>,
+    size: usize,
+}
+
+impl SampleWindow {
+    pub fn of_size(size: usize) -> SampleWindow {
+        SampleWindow {
+            samples: VecDeque::with_capacity(size),
+            size: size,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Add a sample, evicting the oldest one if the window is full.
+    pub fn push(&mut self, sample: f64) {
+        if self.samples.len() == self.size {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    /// Push two synthetic samples whose mean and sample standard deviation
+    /// are the given ones. These get pushed out as real samples come in.
+    pub fn seed(&mut self, mean: f64, stddev: f64) {
+        let offset = stddev / SQRT_2;
+        self.push(mean - offset);
+        self.push(mean + offset);
+    }
+
+    /// The mean of the samples in the window. We need at least two samples
+    /// before we consider this meaningful.
+    pub fn mean(&self) -> Option<f64> {
+        if self.samples.len() < 2 {
+            None
+        } else {
+            Some(self.samples.iter().sum::<f64>() / self.samples.len() as f64)
+        }
+    }
+
+    /// The (unbiased) sample variance of the samples in the window.
+    pub fn variance(&self) -> Option<f64> {
+        self.mean().map(|mean| {
+            let sq_dev = self.samples
+                .iter()
+                .map(|x| (x - mean) * (x - mean))
+                .sum::<f64>();
+            sq_dev / (self.samples.len() - 1) as f64
+        })
+    }
+}
+
+
+/// Beyond this point we switch from `erfc` to its asymptotic continued
+/// fraction, which is accurate to machine precision out here.
+const ERFC_CUTOVER: f64 = 3f64;
+
+/// Number of terms to evaluate in the continued fraction for `erfcx`.
+const ERFCX_TERMS: u32 = 40;
+
+/// The scaled complementary error function `exp(x^2) * erfc(x)` for
+/// `x >= ERFC_CUTOVER`, evaluated using its continued fraction expansion.
+fn erfcx_tail(x: f64) -> f64 {
+    let mut t = x;
+    for n in (1..ERFCX_TERMS + 1).rev() {
+        t = x + (n as f64 / 2f64) / t;
+    }
+    1f64 / (t * PI.sqrt())
+}
+
+/// `-log10(P(Z > z))` for a standard normal `Z`, computed directly from the
+/// log of the survival function. Unlike `-log10(1 - cdf(z))`, this doesn't
+/// saturate at infinity once the cdf rounds to 1, and stays strictly
+/// increasing in `z` for as long as `z * z` fits in an `f64`.
+pub fn normal_phi(z: f64) -> f64 {
+    let x = z / SQRT_2;
+    if x < ERFC_CUTOVER {
+        -(0.5f64 * erfc(x)).log10()
+    } else {
+        // log10(erfc(x) / 2) = log10(erfcx(x)) - x^2 log10(e) - log10(2)
+        x * x * LOG10_E + 2f64.log10() - erfcx_tail(x).log10()
+    }
+}
+
+
+/// Models inter-arrival times as normally distributed.
+#[derive(Clone, Debug)]
+pub struct NormalEstimator {
+    window: SampleWindow,
+}
+
+impl NormalEstimator {
+    pub fn of_size(size: usize) -> NormalEstimator {
+        NormalEstimator { window: SampleWindow::of_size(size) }
+    }
+}
+
+impl InterArrivalEstimator for NormalEstimator {
+    fn update(&mut self, sample: f64) {
+        self.window.push(sample);
+    }
+
+    fn seed(&mut self, mean: f64, stddev: f64) {
+        self.window.seed(mean, stddev);
+    }
+
+    fn phi(&self, elapsed: f64, min_stddev: f64) -> Option<f64> {
+        match (self.window.mean(), self.window.variance()) {
+            (Some(mean), Some(var)) => {
+                let stddev = var.sqrt().max(min_stddev);
+                if stddev > 0f64 {
+                    Some(normal_phi((elapsed - mean) / stddev))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn mean(&self) -> Option<f64> {
+        self.window.mean()
+    }
+
+    fn variance(&self) -> Option<f64> {
+        self.window.variance()
+    }
+
+    fn box_clone(&self) -> Box<dyn InterArrivalEstimator> {
+        Box::new(self.clone())
+    }
+}
+
+
+/// Models inter-arrival times as exponentially distributed, the way Cassandra
+/// does. This only needs the mean, and phi is simply proportional to how many
+/// mean intervals we have gone without hearing from the member.
+#[derive(Clone, Debug)]
+pub struct ExponentialEstimator {
+    window: SampleWindow,
+}
+
+impl ExponentialEstimator {
+    pub fn of_size(size: usize) -> ExponentialEstimator {
+        ExponentialEstimator { window: SampleWindow::of_size(size) }
+    }
+}
+
+impl InterArrivalEstimator for ExponentialEstimator {
+    fn update(&mut self, sample: f64) {
+        self.window.push(sample);
+    }
+
+    fn seed(&mut self, mean: f64, _stddev: f64) {
+        // The spread of an exponential distribution is fixed by its mean.
+        self.window.seed(mean, 0f64);
+    }
+
+    fn phi(&self, elapsed: f64, _min_stddev: f64) -> Option<f64> {
+        match self.window.mean() {
+            Some(mean) if mean > 0f64 => Some(LOG10_E * elapsed.max(0f64) / mean),
+            _ => None,
+        }
+    }
+
+    fn mean(&self) -> Option<f64> {
+        self.window.mean()
+    }
+
+    fn variance(&self) -> Option<f64> {
+        self.window.mean().map(|mean| mean * mean)
+    }
+
+    fn box_clone(&self) -> Box<dyn InterArrivalEstimator> {
+        Box::new(self.clone())
+    }
+}
+
+
+/// Models inter-arrival times as log-normally distributed. The window holds
+/// the logarithms of the samples, which are then normally distributed.
+#[derive(Clone, Debug)]
+pub struct LogNormalEstimator {
+    log_window: SampleWindow,
+}
+
+impl LogNormalEstimator {
+    pub fn of_size(size: usize) -> LogNormalEstimator {
+        LogNormalEstimator { log_window: SampleWindow::of_size(size) }
+    }
+
+    fn params(&self) -> Option<(f64, f64)> {
+        match (self.log_window.mean(), self.log_window.variance()) {
+            (Some(mu), Some(var)) => Some((mu, var)),
+            _ => None,
+        }
+    }
+}
+
+impl InterArrivalEstimator for LogNormalEstimator {
+    fn update(&mut self, sample: f64) {
+        // A zero inter-arrival time can only be a measurement artifact, and
+        // has no logarithm, so we skip it.
+        if sample > 0f64 {
+            self.log_window.push(sample.ln());
+        }
+    }
+
+    fn seed(&mut self, mean: f64, stddev: f64) {
+        if mean <= 0f64 {
+            return;
+        }
+        // The parameters of the log-normal distribution with these moments.
+        let cv = stddev / mean;
+        let var = (1f64 + cv * cv).ln();
+        self.log_window.seed(mean.ln() - var / 2f64, var.sqrt());
+    }
+
+    fn phi(&self, elapsed: f64, min_stddev: f64) -> Option<f64> {
+        match self.params() {
+            Some((mu, var)) => {
+                // Translate the floor on the standard deviation of the
+                // inter-arrival times into one on the standard deviation of
+                // their logarithms, taking the median as the scale.
+                let cv = min_stddev / mu.exp();
+                let stddev = var.sqrt().max((1f64 + cv * cv).ln().sqrt());
+                if stddev == 0f64 {
+                    None
+                } else if elapsed <= 0f64 {
+                    Some(0f64)
+                } else {
+                    Some(normal_phi((elapsed.ln() - mu) / stddev))
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn mean(&self) -> Option<f64> {
+        self.params().map(|(mu, var)| (mu + var / 2f64).exp())
+    }
+
+    fn variance(&self) -> Option<f64> {
+        self.params().map(|(mu, var)| {
+            (var.exp() - 1f64) * (2f64 * mu + var).exp()
+        })
+    }
+
+    fn box_clone(&self) -> Box<dyn InterArrivalEstimator> {
+        Box::new(self.clone())
+    }
+}
+
+
+/// A normal distribution maintained using exponentially weighted moving
+/// averages of the mean and variance. Unlike the windowed estimators, this
+/// does not keep any samples around, and older observations are never fully
+/// forgotten.
+#[derive(Clone, Debug)]
+pub struct InterArrivalDistribution {
+    mu: f64,
+    sigma: f64,
+    var: f64,
+    alpha: f64,
+}
+
+impl InterArrivalDistribution {
+    pub fn new(mu: f64, sigma: f64, alpha: f64) -> InterArrivalDistribution {
+        InterArrivalDistribution {
+            mu: mu,
+            sigma: sigma,
+            var: sigma * sigma,
+            alpha: alpha,
+        }
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.mu
+    }
+
+    pub fn stddev(&self) -> f64 {
+        self.sigma
+    }
+
+    pub fn variance(&self) -> f64 {
+        self.var
+    }
+
+    pub fn update(&mut self, val: f64) {
+
+        let new_mean = self.alpha * self.mean() + (1f64 - self.alpha) * val;
+
+        let new_var = self.alpha * self.variance() +
+            (1f64 - self.alpha) * (val - self.mean()) * (val - new_mean);
+
+        debug!("Update with {:0.4}, mean: {:0.4} -> {:0.4}, var: {:0.4}  -> {:0.4}",
+               val, self.mean(), new_mean, self.variance(), new_var);
+        self.mu = new_mean;
+        self.var = new_var;
+        self.sigma = new_var.sqrt();
+    }
+
+    /// How many standard deviations away from the mean `val` is, taking the
+    /// standard deviation to be at least `min_stddev`.
+    pub fn z_score(&self, val: f64, min_stddev: f64) -> Option<f64> {
+        let stddev = self.stddev().max(min_stddev);
+        if stddev == 0f64 {
+            None
+        } else {
+            Some((val - self.mean()) / stddev)
+        }
+    }
+}
+
+
+/// Adapts `InterArrivalDistribution` to the `InterArrivalEstimator` interface.
+/// The distribution is seeded by the first sample.
+#[derive(Clone, Debug)]
+pub struct EwmaEstimator {
+    alpha: f64,
+    distribution: Option<InterArrivalDistribution>,
+}
+
+impl EwmaEstimator {
+    pub fn new(alpha: f64) -> EwmaEstimator {
+        EwmaEstimator {
+            alpha: alpha,
+            distribution: None,
+        }
+    }
+}
+
+impl InterArrivalEstimator for EwmaEstimator {
+    fn update(&mut self, sample: f64) {
+        if let Some(ref mut dist) = self.distribution {
+            dist.update(sample);
+            return;
+        }
+        self.distribution = Some(InterArrivalDistribution::new(sample, 0f64, self.alpha));
+    }
+
+    fn seed(&mut self, mean: f64, stddev: f64) {
+        self.distribution = Some(InterArrivalDistribution::new(mean, stddev, self.alpha));
+    }
+
+    fn phi(&self, elapsed: f64, min_stddev: f64) -> Option<f64> {
+        self.distribution
+            .as_ref()
+            .and_then(|dist| dist.z_score(elapsed, min_stddev))
+            .map(normal_phi)
+    }
+
+    fn mean(&self) -> Option<f64> {
+        self.distribution.as_ref().map(|d| d.mean())
+    }
+
+    fn variance(&self) -> Option<f64> {
+        self.distribution.as_ref().map(|d| d.variance())
+    }
+
+    fn box_clone(&self) -> Box<dyn InterArrivalEstimator> {
+        Box::new(self.clone())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::TestResult;
+
+    fn fill(est: &mut dyn InterArrivalEstimator, samples: &[f64]) {
+        for s in samples {
+            est.update(*s);
+        }
+    }
+
+    #[test]
+    fn test_exponential_phi_is_linear_in_elapsed_time() {
+        let mut est = ExponentialEstimator::of_size(4);
+        fill(&mut est, &[1f64, 1f64, 1f64, 1f64]);
+        let phi1 = est.phi(1f64, 0f64).unwrap();
+        let phi3 = est.phi(3f64, 0f64).unwrap();
+        assert!((phi1 - LOG10_E).abs() <= 1e-9);
+        assert!((phi3 - 3f64 * phi1).abs() <= 1e-9);
+    }
+
+    #[test]
+    fn test_lognormal_is_gentler_than_normal_on_long_tails() {
+        let samples = [0.8f64, 1.0f64, 1.2f64, 0.9f64, 1.1f64];
+        let mut normal = NormalEstimator::of_size(5);
+        let mut lognormal = LogNormalEstimator::of_size(5);
+        fill(&mut normal, &samples);
+        fill(&mut lognormal, &samples);
+
+        assert!((lognormal.mean().unwrap() - normal.mean().unwrap()).abs() <= 0.05);
+        assert!(lognormal.phi(1.8f64, 0f64).unwrap() < normal.phi(1.8f64, 0f64).unwrap());
+    }
+
+    #[test]
+    fn test_ewma_seeds_with_first_sample() {
+        let mut est = EwmaEstimator::new(0.9f64);
+        assert!(est.mean().is_none());
+        est.update(2f64);
+        assert_eq!(est.mean(), Some(2f64));
+        // No spread yet, so no phi either.
+        assert!(est.phi(3f64, 0f64).is_none());
+        assert!(est.phi(3f64, 0.1f64).is_some());
+    }
+
+    #[test]
+    fn test_seed_sets_the_prior_of_every_estimator() {
+        let kinds = [EstimatorKind::Normal, EstimatorKind::Exponential,
+                     EstimatorKind::LogNormal, EstimatorKind::Ewma(0.9f64)];
+        for kind in kinds.iter() {
+            let mut est = kind.build(10);
+            est.seed(2f64, 0.5f64);
+            assert!((est.mean().unwrap() - 2f64).abs() <= 1e-9, "{:?}", kind);
+            let expected_var = match *kind {
+                EstimatorKind::Exponential => 4f64,
+                _ => 0.25f64,
+            };
+            assert!((est.variance().unwrap() - expected_var).abs() <= 1e-9, "{:?}", kind);
+            assert!(est.phi(2f64, 0f64).unwrap() < est.phi(4f64, 0f64).unwrap(), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn test_min_stddev_gives_phi_for_perfectly_regular_pings() {
+        let mut est = NormalEstimator::of_size(3);
+        fill(&mut est, &[1f64, 1f64, 1f64]);
+        assert!(est.phi(1.5f64, 0f64).is_none());
+        let phi = est.phi(1.5f64, 0.5f64).unwrap();
+        assert!((phi - normal_phi(1f64)).abs() <= 1e-9);
+    }
+
+    #[test]
+    fn test_normal_phi_matches_naive_formula_near_the_mean() {
+        // Values of -log10(1 - cdf(z)) for small z, where the naive formula
+        // is still accurate.
+        let expected = [(0f64, -0.5f64.log10()), (1f64, 0.79955), (2f64, 1.64302), (3f64, 2.86970)];
+        for &(z, phi) in expected.iter() {
+            assert!((normal_phi(z) - phi).abs() <= 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_normal_phi_stays_finite_far_out() {
+        let mut prev = normal_phi(8f64);
+        for &z in [10f64, 40f64, 1e3, 1e6, 1e9].iter() {
+            let phi = normal_phi(z);
+            assert!(phi.is_finite());
+            assert!(phi > prev);
+            prev = phi;
+        }
+    }
+
+    quickcheck! {
+        fn prop_phi_is_monotonic_over_time(samples: Vec<u8>, t1: u32, t2: u32) -> TestResult {
+            if t1 == t2 {
+                return TestResult::discard();
+            }
+            let (t1, t2) = if t1 < t2 { (t1, t2) } else { (t2, t1) };
+            // Elapsed times in milliseconds, going out to days.
+            let (e1, e2) = (t1 as f64 * 1e-3, t2 as f64 * 1e-3);
+
+            let kinds = [EstimatorKind::Normal, EstimatorKind::Exponential,
+                         EstimatorKind::LogNormal, EstimatorKind::Ewma(0.9f64)];
+            for kind in kinds.iter() {
+                let mut est = kind.build(samples.len().max(3));
+                for s in samples.iter() {
+                    est.update(0.5f64 + *s as f64 / 100f64);
+                }
+                let (mean, phi1, phi2) = match (est.mean(), est.phi(e1, 0f64), est.phi(e2, 0f64)) {
+                    (Some(mean), Some(phi1), Some(phi2)) => (mean, phi1, phi2),
+                    _ => continue,
+                };
+                if !phi1.is_finite() || !phi2.is_finite() || phi1 > phi2 {
+                    return TestResult::failed();
+                }
+                if e1 >= mean && phi1 >= phi2 {
+                    return TestResult::failed();
+                }
+            }
+            TestResult::passed()
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use tokio::sync::broadcast::{self, Receiver, Sender};
+
+use crate::member::{MemberID, MemberStatus};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MembershipEventKind {
+    /// We heard of a member for the first time (or for the first time since
+    /// reaping it).
+    MemberJoined,
+    MemberSuspected,
+    MemberFailed,
+    /// A suspected, dead or departed member is alive again.
+    MemberRecovered,
+    MemberLeft,
+    /// Sent for every member on every ping interval.
+    PhiUpdated,
+}
+
+impl MembershipEventKind {
+    /// The event to emit when a member goes from status `from` to `to`, if
+    /// any.
+    pub fn from_transition(from: MemberStatus, to: MemberStatus) -> Option<MembershipEventKind> {
+        match (from, to) {
+            (MemberStatus::Alive, MemberStatus::Alive) => None,
+            (_, MemberStatus::Alive) => Some(MembershipEventKind::MemberRecovered),
+            (_, MemberStatus::Suspect) => Some(MembershipEventKind::MemberSuspected),
+            (_, MemberStatus::Dead) => Some(MembershipEventKind::MemberFailed),
+            (_, MemberStatus::Left) => Some(MembershipEventKind::MemberLeft),
+        }
+    }
+}
+
+/// Something that happened to a member, along with its phi at the time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MembershipEvent {
+    pub kind: MembershipEventKind,
+    pub id: MemberID,
+    pub phi: Option<f64>,
+}
+
+impl MembershipEvent {
+    pub fn new(kind: MembershipEventKind, id: MemberID, phi: Option<f64>) -> MembershipEvent {
+        MembershipEvent {
+            kind: kind,
+            id: id,
+            phi: phi,
+        }
+    }
+}
+
+/// Fans membership events out to any number of subscribers. Clones share the
+/// same set of subscribers, so one can subscribe through a clone from another
+/// thread than the one publishing. Events are kept for up to `capacity`
+/// events back, so a subscriber that falls further behind than that, or
+/// stops reading altogether, misses the oldest ones (and is told so with
+/// `RecvError::Lagged`) rather than holding on to ever more of them.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: Sender<MembershipEvent>,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> EventBus {
+        let (sender, _) = broadcast::channel(capacity);
+        EventBus { sender: sender }
+    }
+
+    /// A stream of all events published from now on.
+    pub fn subscribe(&self) -> Receiver<MembershipEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Send `event` to every subscriber. With no subscribers, it goes
+    /// nowhere.
+    pub fn publish(&self, event: MembershipEvent) {
+        let _ = self.sender.send(event);
+    }
+}
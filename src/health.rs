@@ -0,0 +1,91 @@
+use std::cmp;
+use std::time::Duration;
+
+/// How far we trust ourselves to judge others, after Lifeguard's local
+/// health multiplier. A process that is starved for CPU misses the Acks to
+/// its pings and runs its ticks late, and would soon declare everyone dead.
+/// Every such sign of trouble raises the score, up to a maximum, and every
+/// probe that is answered lowers it again. Like in Lifeguard, it is our
+/// timeouts that are scaled by `multiplier()`: how long we wait on probes,
+/// and how long a suspect has to prove us wrong. Members also get to be
+/// silent for as much longer as we wait on probes before we suspect them.
+/// Phi being a logarithm, the suspicion threshold is left alone.
+#[derive(Clone, Debug)]
+pub struct LocalHealth {
+    score: u32,
+    max_score: u32,
+}
+
+impl LocalHealth {
+    /// A healthy score, which never goes above `max_score`. A `max_score` of
+    /// 0 means we always trust ourselves.
+    pub fn new(max_score: u32) -> LocalHealth {
+        LocalHealth {
+            score: 0,
+            max_score: max_score,
+        }
+    }
+
+    /// 0 when we are healthy, higher the less we are.
+    pub fn score(&self) -> u32 {
+        self.score
+    }
+
+    /// What to scale our timeouts by.
+    pub fn multiplier(&self) -> u32 {
+        self.score + 1
+    }
+
+    /// A member answered our ping.
+    pub fn probe_answered(&mut self) {
+        self.score = self.score.saturating_sub(1);
+    }
+
+    /// A member didn't answer our ping in time.
+    pub fn probe_missed(&mut self) {
+        self.worsen();
+    }
+
+    /// We got around to something `lag` later than we should have. Only lags
+    /// over `tolerance` count against us.
+    pub fn lagged(&mut self, lag: Duration, tolerance: Duration) {
+        if lag > tolerance {
+            self.worsen();
+        }
+    }
+
+    fn worsen(&mut self) {
+        self.score = cmp::min(self.score + 1, self.max_score);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_is_bounded() {
+        let mut health = LocalHealth::new(2);
+        assert_eq!(health.multiplier(), 1);
+        for _ in 0..5 {
+            health.probe_missed();
+        }
+        assert_eq!(health.score(), 2);
+        assert_eq!(health.multiplier(), 3);
+        for _ in 0..5 {
+            health.probe_answered();
+        }
+        assert_eq!(health.score(), 0);
+    }
+
+    #[test]
+    fn test_only_lags_over_the_tolerance_count() {
+        let mut health = LocalHealth::new(8);
+        let tolerance = Duration::from_millis(200);
+        health.lagged(Duration::from_millis(200), tolerance);
+        assert_eq!(health.score(), 0);
+        health.lagged(Duration::from_millis(201), tolerance);
+        assert_eq!(health.score(), 1);
+    }
+}
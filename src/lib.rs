@@ -1,71 +1,123 @@
-extern crate bytes;
-extern crate futures;
-extern crate tokio_io;
 #[macro_use]
 extern crate log;
-extern crate tokio_core;
-extern crate tokio_proto;
-extern crate tokio_service;
-extern crate statrs;
 
-extern crate protobuf;
-extern crate time;
-extern crate rand;
+#[cfg(test)]
+#[macro_use]
+extern crate quickcheck;
 
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 use std::io;
-use std::net::SocketAddr;
-use std::time::Instant;
+use std::net::{self, SocketAddr};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use std::cmp;
+use std::future::{self, Future};
 
 use rand::{thread_rng, seq, Rng};
-use futures::{Stream, stream};
-use tokio_core::net::{UdpSocket, UdpCodec};
-use tokio_core::reactor::{Core, Interval};
-use proto::msg::{Gossip, Member};
-use member::{MemberState, MemberID};
+use tokio::net::UdpSocket;
+use tokio::runtime;
+use tokio::sync::oneshot;
+use tokio::sync::broadcast::Receiver;
+use tokio::time::{interval_at, sleep_until};
+use crate::proto::msg::{Gossip, Member};
 use protobuf::core::{Message, parse_from_bytes};
 
 pub mod proto;
 pub mod util;
 pub mod config;
 pub mod member;
+pub mod estimator;
+pub mod event;
+pub mod health;
 
 pub use config::*;
 pub use util::*;
 pub use member::*;
+pub use estimator::*;
+pub use event::*;
+pub use health::*;
 
 
+/// A failure detector. Clones share the same state, so one clone can be run
+/// while others are used to query it from other threads.
+#[derive(Clone)]
 pub struct PhiFD {
-    state: Rc<RefCell<FDState>>,
+    state: Arc<Mutex<FDState>>,
 }
 
 
 enum FDEvent {
     PingOut(Vec<(SocketAddr, Gossip)>), // optimize this, gossip is the same for all
+    /// The ping-out is put off by this long, see `Config::ticker_delay`.
+    PingDelayed(Duration),
+    /// A tick came while the ping-out was still put off, so we skip it.
+    PingPending,
     AckOut(SocketAddr, Gossip),
+    LeaveOut(Vec<(SocketAddr, Gossip)>),
+    /// Indirect probing, see `GossipType::PingReq`.
+    ProbeOut(Vec<(SocketAddr, Gossip)>),
     StateUpdated,
     Unexpected(String),
 }
 
-use FDEvent::*;
+use self::FDEvent::*;
+
+/// A ping we are waiting on an answer to.
+struct Probe {
+    deadline: Instant,
+    /// Whether we have already asked others to probe the member for us.
+    indirect: bool,
+}
 
+/// A member we are probing on behalf of others, see `GossipType::PingReq`.
+struct Relay {
+    deadline: Instant,
+    /// Where to send the IndirectAcks to.
+    requesters: Vec<SocketAddr>,
+}
 
 struct FDState {
     members: HashMap<MemberID, MemberState>,
+    /// Members we have reaped recently, see `FDState::reap`.
+    tombstones: HashMap<MemberID, Tombstone>,
+    /// Members we pinged and haven't heard back from, see
+    /// `FDState::expire_probes`.
+    probes: HashMap<MemberID, Probe>,
+    /// Members we are probing for others, by the address we probe them at.
+    relays: HashMap<SocketAddr, Relay>,
+    health: LocalHealth,
     config: Config,
+    /// Who we are, see `Config::node_id`.
+    node_id: String,
+    /// Our startup time in milliseconds since the epoch, which tells our
+    /// peers apart the heartbeats from before and after a restart.
+    incarnation: u64,
     heartbeat: u64,
+    events: EventBus,
+    /// How many datagrams we have dropped for not being gossip we understand.
+    decode_failures: u64,
 }
 
 impl FDState {
     fn new(config: Option<Config>) -> FDState {
         let config = config.unwrap_or(Config::default());
+        let node_id = config.node_id();
+        let health = LocalHealth::new(config.max_local_health_score);
+        let events = EventBus::new(config.event_capacity);
         FDState {
             members: HashMap::new(),
+            tombstones: HashMap::new(),
+            probes: HashMap::new(),
+            relays: HashMap::new(),
+            health: health,
             config: config,
+            node_id: node_id,
+            incarnation: startup_incarnation(),
             heartbeat: 0u64,
+            events: events,
+            decode_failures: 0,
         }
     }
 
@@ -73,77 +125,523 @@ impl FDState {
         self.heartbeat += 1;
     }
 
+    /// Move every member along its lifecycle given how suspicious it looks
+    /// at `now`, and let subscribers know of the new phi values.
+    fn update_statuses(&mut self, now: Instant) {
+        let threshold = self.config.suspicion_threshold;
+        let suspect_timeout = self.suspect_timeout();
+        let extra_pause = self.extra_pause();
+        let opinion_max_age = self.config.opinion_max_age;
+        for memberstate in self.members.values_mut() {
+            memberstate.expire_opinions(now, opinion_max_age);
+            let id = memberstate.get_id();
+            let phi = memberstate.damped_phi(now, extra_pause);
+            let before = memberstate.status();
+            if let Some(status) = memberstate.update_status(now, threshold, suspect_timeout,
+                                                            extra_pause) {
+                info!("member {} is now {:?}", id, status);
+                if let Some(kind) = MembershipEventKind::from_transition(before, status) {
+                    self.events.publish(MembershipEvent::new(kind, id.clone(), phi));
+                }
+            }
+            self.events.publish(MembershipEvent::new(MembershipEventKind::PhiUpdated, id, phi));
+        }
+    }
+
+    fn snapshot(&self, at: Instant) -> Vec<MemberSnapshot> {
+        self.members.values().map(|m| m.snapshot(at)).collect()
+    }
+
     fn cur_heartbeat(&self) -> u64 {
         self.heartbeat
     }
 
+    fn incarnation(&self) -> u64 {
+        self.incarnation
+    }
+
+    /// Gossip of the given kind, carrying as much of our membership list as
+    /// fits, see `FDState::fill_members`.
+    fn gossip(&self, typ: GossipType) -> Gossip {
+        let mut gossip = self.bare_gossip(typ);
+        self.fill_members(&mut gossip, Instant::now());
+        gossip
+    }
+
+    /// Like `gossip`, but about `target`, see `Gossip.target`.
+    fn gossip_about(&self, typ: GossipType, target: Member) -> Gossip {
+        let mut gossip = self.bare_gossip(typ);
+        gossip.set_target(target);
+        self.fill_members(&mut gossip, Instant::now());
+        gossip
+    }
+
+    /// Gossip of the given kind about nobody but us.
+    fn bare_gossip(&self, typ: GossipType) -> Gossip {
+        let mut us = self.own_member();
+        us.set_left(typ == GossipType::Leave);
+        let mut gossip = make_gossip(
+            self.incarnation(),
+            self.cur_heartbeat(),
+            vec![].into_iter(),
+            typ,
+        );
+        gossip.set_node_id(self.node_id.clone());
+        if let Some(addr) = self.config.advertised_addr() {
+            gossip.set_advertise_addr(addr.to_string());
+        }
+        gossip.set_sender(us);
+        gossip
+    }
+
+    /// Add as many members to `gossip` as fit in `Config::max_gossip_size`.
+    /// Those that changed in the last `RECENT_CHANGE_ROUNDS` ping intervals
+    /// (as of `now`) go first, the most recent ones first, so that news
+    /// spreads fast. They only get up to half the room, though, and the rest
+    /// goes to a random sample of everyone else, so that every member gets
+    /// gossiped about sooner or later, and peers that only ever get part of
+    /// the list still hear of everyone.
+    fn fill_members(&self, gossip: &mut Gossip, now: Instant) {
+        let interval = cmp::max(self.config.ping_interval.as_millis(), 1);
+        let rounds_ago = |m: &MemberState| {
+            now.saturating_duration_since(m.changed_at()).as_millis() / interval
+        };
+        let (mut recent, mut rest): (Vec<_>, Vec<_>) = self.members
+            .values()
+            .partition(|m| rounds_ago(m) < RECENT_CHANGE_ROUNDS as u128);
+        // News from the same round comes in no particular order, so that a
+        // burst of it doesn't crowd out the same members every time.
+        let mut rng = thread_rng();
+        rng.shuffle(&mut recent);
+        recent.sort_by_key(|m| rounds_ago(m));
+
+        let max_size = self.config.max_gossip_size;
+        let mut size = gossip.compute_size() as usize;
+        let recent_max_size = size + max_size.saturating_sub(size) / 2;
+        let extra_pause = self.extra_pause();
+        let mut add = |memberstate: &MemberState, size: &mut usize, max_size: usize| {
+            // Working out a member's record takes its phi, so we don't bother
+            // once not even the smallest of records fits.
+            if *size + MIN_MEMBER_RECORD_SIZE > max_size {
+                return false;
+            }
+            let member = memberstate.gossip_record(now, extra_pause);
+            // The member goes in as a length delimited field of its own.
+            let len = member.compute_size();
+            let added = 1 + protobuf::rt::compute_raw_varint32_size(len) as usize + len as usize;
+            if *size + added > max_size {
+                return false;
+            }
+            *size += added;
+            gossip.mut_members().push(member);
+            true
+        };
+        for memberstate in recent {
+            if !add(memberstate, &mut size, recent_max_size) {
+                rest.push(memberstate);
+            }
+        }
+        rng.shuffle(&mut rest);
+        for memberstate in rest {
+            if size + MIN_MEMBER_RECORD_SIZE > max_size {
+                break;
+            }
+            add(memberstate, &mut size, max_size);
+        }
+    }
+
+    /// Our own record, as we would like peers to have it.
+    fn own_member(&self) -> Member {
+        let addr = self.config.advertised_addr().unwrap_or(self.config.addr);
+        let mut us = member_from_sockaddr(addr);
+        us.set_node_id(self.node_id.clone());
+        us.set_incarnation(self.incarnation());
+        us.set_heartbeat(self.cur_heartbeat());
+        us
+    }
+
+    /// IDs and addresses of up to `k` random members matching `pred`,
+    /// leaving out those that left.
+    fn random_peers<P>(&self, k: usize, pred: P) -> Vec<(MemberID, SocketAddr)>
+    where
+        P: Fn(&MemberState) -> bool,
+    {
+        let mut rng = thread_rng();
+        let candidates = self.members
+            .values()
+            .filter(|m| m.status() != MemberStatus::Left && pred(m))
+            .collect::<Vec<_>>();
+        let k = cmp::min(k, candidates.len());
+
+        seq::sample_iter(&mut rng, candidates.into_iter(), k)
+            .map(|values| {
+                values
+                    .iter()
+                    .map(|v| (v.get_id(), member_addr(v.get_member_ref())))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or(vec![])
+    }
+
     fn with_members(members: Vec<Member>, config: Option<Config>) -> FDState {
         let mut ret = FDState::new(config);
-        let wnd_sz = ret.config.window_size;
         for member in members.into_iter() {
-            let ip = member.get_ip();
-            let port = member.get_port() as u16;
             ret.members.insert(
-                (ip, port),
-                MemberState::from_member(member, wnd_sz),
+                MemberID::of(&member),
+                MemberState::from_member(member, &ret.config),
             );
         }
         ret
     }
 
-    fn merge(&mut self, from_addr: SocketAddr, mut gossip: Gossip) {
+    /// Take in `gossip` that came from `from_addr`. Returns false if we
+    /// ignored it altogether, see `Config::verify_sender_addr`.
+    fn merge(&mut self, from_addr: SocketAddr, mut gossip: Gossip) -> bool {
 
         /* 1. We consider the sender node and the nodes present in the gossip.
-         * 2. For each node considered, we check if their id (ip, port) is
+         * 2. For each node considered, we check if their id (see MemberID) is
          * in our membership list. If not there, we insert an entry. If there,
          * we need to merge the incoming knowledge with what we know. In reality,
          * we merge for both these cases anyway, because the .or_insert() API
          * is convenient. */
 
-        let mut sender = member_from_sockaddr(from_addr).expect("error recovering who pinged us");
-        sender.set_heartbeat(gossip.get_heartbeat());
-
-        // FIXME: don't do this over and over
-        let our_addr = ip_number_and_port_from_sockaddr(self.config.addr).expect(
-            "could not parse our own ip/port?",
-        );
-
+        let mut sender = sender_member(from_addr, &gossip);
+        if self.config.verify_sender_addr && member_addr(&sender) != canonical_addr(from_addr) {
+            return false;
+        }
+        if let Some(GossipType::Leave) = GossipType::from_u32(gossip.get_kind()) {
+            sender.set_left(true);
+        }
 
         // handle the sender
-        let snd_ip = sender.get_ip();
-        let snd_port = sender.get_port() as u16;
-        let snd_addr = (snd_ip, snd_port);
-
-        if our_addr != snd_addr {
-            let heartbeat = sender.get_heartbeat();
-            let susp = sender.get_suspicion();
-            let wnd_sz = self.config.window_size;
-            self.members
-                .entry(snd_addr)
-                .or_insert_with(move || MemberState::from_member(sender, wnd_sz))
-                .merge(susp, heartbeat);
+        let mut reporter = None;
+        if !self.is_us(&sender) {
+            let addr = member_addr(&sender);
+            if let Some(id) = self.merge_member(sender) {
+                // Hearing from a member at all answers our ping.
+                self.end_probe(&id, addr);
+                if let Some(memberstate) = self.members.get_mut(&id) {
+                    memberstate.set_protocol(gossip.get_version(), gossip.get_capabilities());
+                }
+                // Older peers leave the suspicion of the members they gossip
+                // at 0, which is no opinion of theirs.
+                if gossip.get_capabilities() & CAP_SUSPICION != 0 {
+                    reporter = Some(id);
+                }
+            }
         } else {
             warn!(
                 "We sent a ping to ourselves (us: {:?}, from: {:?})",
-                &our_addr,
-                &snd_addr
+                &self.config.addr,
+                &from_addr
             );
+            // Most likely, we were given our own address as a seed.
+            let ours = MemberID::Addr(canonical_addr(from_addr));
+            if self.members.remove(&ours).is_some() {
+                info!("member {} turns out to be us", &ours);
+            }
         }
 
+        let now = Instant::now();
         for incoming_member in gossip.take_members().into_iter() {
-            let ip = incoming_member.get_ip();
-            let port = incoming_member.get_port() as u16;
-            let addr = (ip, port);
+            if self.is_us(&incoming_member) {
+                continue;
+            }
+            let phi = incoming_member.get_suspicion();
+            let id = self.merge_member(incoming_member);
+            if let (Some(reporter), Some(id)) = (reporter.as_ref(), id) {
+                if *reporter != id {
+                    if let Some(memberstate) = self.members.get_mut(&id) {
+                        memberstate.add_opinion(reporter.clone(), phi, now);
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Whether `member` is this very node.
+    fn is_us(&self, member: &Member) -> bool {
+        if member.has_node_id() {
+            member.get_node_id() == self.node_id
+        } else {
+            let addr = member_addr(member);
+            addr == canonical_addr(self.config.addr) ||
+                self.config.advertised_addr().map(canonical_addr) == Some(addr)
+        }
+    }
+
+    /// The ID to file news about `member` under. News without a node ID may
+    /// still be about a member we know the node ID of, from a peer that
+    /// doesn't, so we look for one at the same address first.
+    fn resolve_id(&self, member: &Member) -> MemberID {
+        let id = MemberID::of(member);
+        if id.is_node() {
+            return id;
+        }
+        let addr = member_addr(member);
+        self.members
+            .values()
+            .find(|m| m.get_id().is_node() && m.get_addr() == addr)
+            .map(|m| m.get_id())
+            .unwrap_or(id)
+    }
+
+    /// Merge what we heard about a single member into our membership list,
+    /// unless it is a member we reaped and the news is no newer than what we
+    /// knew when we did so. Returns the ID the member is known by, if it is
+    /// (still) a member.
+    fn merge_member(&mut self, member: Member) -> Option<MemberID> {
+        let id = self.resolve_id(&member);
+        let incarnation = member.get_incarnation();
+        let heartbeat = member.get_heartbeat();
+
+        if let Some(tombstone) = self.tombstones.get(&id) {
+            if !tombstone.is_superseded_by(incarnation, heartbeat) {
+                debug!("ignoring stale gossip about reaped member {}", &id);
+                return None;
+            }
+        }
+        if self.tombstones.remove(&id).is_some() {
+            info!("reaped member {} is back", &id);
+        }
+
+        // We may have only known the member by its address so far, as a seed
+        // for instance.
+        if id.is_node() {
+            let addr_id = MemberID::Addr(member_addr(&member));
+            if self.members.remove(&addr_id).is_some() {
+                info!("member {} turns out to be {}", &addr_id, &id);
+            }
+        }
+
+        let config = &self.config;
+        let now = Instant::now();
+        let event = match self.members.entry(id.clone()) {
+            Entry::Occupied(mut e) => {
+                let memberstate = e.get_mut();
+                let before = memberstate.status();
+                memberstate.merge(&member);
+                MembershipEventKind::from_transition(before, memberstate.status())
+                    .map(|kind| MembershipEvent::new(kind, id.clone(), memberstate.phi(now)))
+            }
+            Entry::Vacant(e) => {
+                let memberstate = e.insert(MemberState::from_member(member, config));
+                let kind = match memberstate.status() {
+                    MemberStatus::Left => MembershipEventKind::MemberLeft,
+                    _ => MembershipEventKind::MemberJoined,
+                };
+                Some(MembershipEvent::new(kind, id.clone(), memberstate.phi(now)))
+            }
+        };
+        if let Some(event) = event {
+            self.events.publish(event);
+        }
+        Some(id)
+    }
+
+    /// Forget members that have been dead (or gone) for longer than the reap
+    /// timeout, leaving tombstones behind for them. Also drop tombstones that
+    /// have outlived the grace period.
+    fn reap(&mut self, now: Instant) {
+        let reap_timeout = self.config.reap_timeout;
+        let reapable = self.members
+            .values()
+            .filter(|m| m.is_reapable(now, reap_timeout))
+            .map(|m| m.get_id())
+            .collect::<Vec<_>>();
+
+        let expires_at = now + self.config.tombstone_grace_period;
+        for id in reapable {
+            if let Some(memberstate) = self.members.remove(&id) {
+                info!("reaping member {}", id);
+                let member = memberstate.get_member_ref();
+                let tombstone = Tombstone::new(
+                    member.get_incarnation(),
+                    member.get_heartbeat(),
+                    expires_at,
+                );
+                self.tombstones.insert(id, tombstone);
+            }
+        }
+
+        self.tombstones.retain(|_, tombstone| !tombstone.is_expired(now));
+    }
+
+    /// Start waiting on the members `ids` to answer the pings we are sending
+    /// them, unless we already are.
+    fn start_probes<I>(&mut self, ids: I, now: Instant)
+    where
+        I: Iterator<Item = MemberID>,
+    {
+        let deadline = now + self.probe_timeout();
+        for id in ids {
+            self.probes.entry(id).or_insert(Probe {
+                deadline: deadline,
+                indirect: false,
+            });
+        }
+    }
+
+    /// Stop waiting on the member known by `id`, or by its address `addr`
+    /// until now.
+    fn end_probe(&mut self, id: &MemberID, addr: SocketAddr) {
+        let by_id = self.probes.remove(id).is_some();
+        let by_addr = self.probes.remove(&MemberID::Addr(addr)).is_some();
+        if by_id || by_addr {
+            self.health.probe_answered();
+        }
+    }
+
+    /// How long to wait on a probe, given how healthy we are.
+    fn probe_timeout(&self) -> Duration {
+        self.config.probe_timeout * self.health.multiplier()
+    }
+
+    /// How much longer than usual we let members be silent before we suspect
+    /// them, given how healthy we are: as much longer as we wait on probes.
+    /// Phi growing faster than linearly with the silence, this holds back
+    /// the suspicions of an unhealthy node without silencing them.
+    fn extra_pause(&self) -> Duration {
+        self.probe_timeout() - self.config.probe_timeout
+    }
+
+    /// How long a member stays a suspect before we declare it dead, given
+    /// how healthy we are. The less healthy we are, the longer we give our
+    /// suspicions to be proven wrong.
+    fn suspect_timeout(&self) -> Duration {
+        self.config.suspect_timeout * self.health.multiplier()
+    }
+
+    /// We got around to something `lag` later than we should have.
+    fn lagged(&mut self, lag: Duration) {
+        let tolerance = self.config.lag_tolerance;
+        self.health.lagged(lag, tolerance);
+        if lag > tolerance {
+            warn!("running {:?} late, local health score now {}", lag, self.health.score());
+        }
+    }
+
+    /// When the next probe or relay times out, if any.
+    fn next_probe_deadline(&self) -> Option<Instant> {
+        self.probes
+            .values()
+            .map(|p| p.deadline)
+            .chain(self.relays.values().map(|r| r.deadline))
+            .min()
+    }
 
-            if addr != our_addr {
-                let susp = incoming_member.get_suspicion();
-                let heartbeat = incoming_member.get_heartbeat();
-                let wnd_sz = self.config.window_size;
-                self.members
-                    .entry(addr)
-                    .or_insert_with(move || MemberState::from_member(incoming_member, wnd_sz))
-                    .merge(susp, heartbeat);
+    /// Deal with the probes that have timed out by `now`. We ask up to
+    /// `num_indirect_probes` other members to probe those that didn't answer
+    /// our ping, and return the PingReq gossip to do so. Those that answer
+    /// neither us nor them are left to their suspicion.
+    fn expire_probes(&mut self, now: Instant) -> Vec<(SocketAddr, Gossip)> {
+        self.relays.retain(|_, relay| relay.deadline > now);
+
+        let expired = self.probes
+            .iter()
+            .filter(|&(_, probe)| probe.deadline <= now)
+            .map(|(id, probe)| (id.clone(), probe.indirect))
+            .collect::<Vec<_>>();
+
+        let k = self.config.num_indirect_probes as usize;
+        let mut ping_reqs = vec![];
+        for (id, indirect) in expired {
+            // Members we already suspect are likely to be gone for good, and
+            // their silence says nothing about our own health.
+            let alive = self.members
+                .get(&id)
+                .map(|m| m.status() == MemberStatus::Alive)
+                .unwrap_or(false);
+            if !indirect && alive {
+                self.health.probe_missed();
+            }
+            let target = match self.members.get(&id) {
+                Some(memberstate) if !indirect => memberstate.get_member_ref().clone(),
+                _ => {
+                    debug!("giving up on probing member {}", &id);
+                    self.probes.remove(&id);
+                    continue;
+                }
+            };
+            let helpers = self.random_peers(k, |m| m.get_id() != id && m.supports(CAP_PING_REQ));
+            if helpers.is_empty() {
+                debug!("nobody to ask to probe member {} for us", &id);
+                self.probes.remove(&id);
+                continue;
             }
+
+            info!("member {} didn't answer, asking {} others to probe it", &id, helpers.len());
+            let gossip = self.gossip_about(GossipType::PingReq, target);
+            ping_reqs.extend(helpers.into_iter().map(|(_, addr)| (addr, gossip.clone())));
+            self.probes.insert(id, Probe {
+                deadline: now + self.probe_timeout(),
+                indirect: true,
+            });
+        }
+        ping_reqs
+    }
+
+    /// Probe the target of `gossip`, a PingReq that came from `from_addr`,
+    /// on its sender's behalf. Returns the ping to send, unless we are
+    /// already probing the target anyway.
+    fn relay_ping_req(
+        &mut self,
+        from_addr: SocketAddr,
+        gossip: &Gossip,
+        now: Instant,
+    ) -> Option<(SocketAddr, Gossip)> {
+        let target = gossip.get_target();
+        if !gossip.has_target() || self.is_us(target) {
+            return None;
+        }
+        let addr = member_addr(target);
+        let deadline = now + self.probe_timeout();
+        let relay = self.relays.entry(addr).or_insert(Relay {
+            deadline: deadline,
+            requesters: vec![],
+        });
+        let probing = !relay.requesters.is_empty();
+        if !relay.requesters.contains(&from_addr) {
+            relay.requesters.push(from_addr);
+        }
+        if probing {
+            None
+        } else {
+            Some((addr, self.gossip(GossipType::Syn)))
+        }
+    }
+
+    /// The IndirectAcks we owe to those who asked us to probe the member at
+    /// `addr`, now that it answered.
+    fn relay_acks(&mut self, addr: SocketAddr) -> Vec<(SocketAddr, Gossip)> {
+        let relay = match self.relays.remove(&addr) {
+            Some(relay) => relay,
+            None => return vec![],
+        };
+        let target = match self.members.values().find(|m| m.get_addr() == addr) {
+            Some(memberstate) => memberstate.get_member_ref().clone(),
+            None => return vec![],
+        };
+        let gossip = self.gossip_about(GossipType::IndirectAck, target);
+        relay
+            .requesters
+            .into_iter()
+            .map(|requester| (requester, gossip.clone()))
+            .collect()
+    }
+
+    /// Take in the target of an IndirectAck: news from a member we had no
+    /// answer from, if by way of someone else. This feeds its suspicion just
+    /// as a direct answer would.
+    fn merge_indirect_ack(&mut self, gossip: &Gossip) {
+        let target = gossip.get_target();
+        if !gossip.has_target() || self.is_us(target) {
+            return;
+        }
+        let addr = member_addr(target);
+        if let Some(id) = self.merge_member(target.clone()) {
+            self.end_probe(&id, addr);
         }
     }
 }
@@ -151,179 +649,1282 @@ impl FDState {
 
 impl PhiFD {
     pub fn new(config: Option<Config>) -> PhiFD {
-        PhiFD { state: Rc::new(RefCell::new(FDState::new(config))) }
+        PhiFD { state: Arc::new(Mutex::new(FDState::new(config))) }
     }
 
     pub fn with_members(members: Vec<Member>, config: Option<Config>) -> PhiFD {
-        PhiFD { state: Rc::new(RefCell::new(FDState::with_members(members, config))) }
+        PhiFD { state: Arc::new(Mutex::new(FDState::with_members(members, config))) }
+    }
+
+    /// A stream of membership events from now on. Events are only produced
+    /// while the detector runs, but one can subscribe from any thread.
+    pub fn subscribe(&self) -> Receiver<MembershipEvent> {
+        self.state.lock().unwrap().events.subscribe()
+    }
+
+    /// What we currently know about every member. This can be called from
+    /// any thread, including while the detector runs on another.
+    pub fn members(&self) -> Vec<MemberSnapshot> {
+        self.state.lock().unwrap().snapshot(Instant::now())
+    }
+
+    /// The ID we go by, see `Config::node_id`.
+    pub fn node_id(&self) -> String {
+        self.state.lock().unwrap().node_id.clone()
+    }
+
+    /// Our local health score, 0 when healthy, see `LocalHealth`.
+    pub fn local_health(&self) -> u32 {
+        self.state.lock().unwrap().health.score()
+    }
+
+    /// How many incoming datagrams were dropped for failing to decode.
+    pub fn decode_failures(&self) -> u64 {
+        self.state.lock().unwrap().decode_failures
     }
 
     fn log_suspicisions(&self) {
         let now = Instant::now();
-        for memberstate in self.state.borrow().members.values() {
+        for memberstate in self.state.lock().unwrap().members.values() {
             if let Some(susp) = memberstate.phi(now) {
-                let (id, port) = memberstate.get_id();
-                info!("phi({}:{})={:4}", id, port, susp);
+                info!("phi({})={:4} ({:?})", memberstate.get_id(), susp, memberstate.status());
             }
         }
     }
 
-    pub fn run(&mut self) {
-        let mut core = Core::new().unwrap();
-        let handle = core.handle();
-        let dur = self.state.borrow().config.ping_interval.clone();
-        let ticker = Interval::new(dur, &handle).unwrap();
-
-        let state = &self.state;
-        let listen_addr = state.borrow().config.addr.clone();
+    /// Start a failure detector with the given initial members on a thread
+    /// of its own. The socket is bound before this returns, so a bad listen
+    /// address is reported right away.
+    pub fn start(members: Vec<Member>, config: Option<Config>) -> io::Result<DetectorHandle> {
+        let config = config.unwrap_or(Config::default());
+        let socket = net::UdpSocket::bind(&config.addr)?;
+        let local_addr = socket.local_addr()?;
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let fd = PhiFD::with_members(members, Some(config));
+        let runner = fd.clone();
 
-        let conn = UdpSocket::bind(&listen_addr, &handle).unwrap();
-        let (sink, stream) = conn.framed(GossipCodec).split();
+        let thread = thread::Builder::new()
+            .name("phifd".to_string())
+            .spawn(move || {
+                // Dropping the sender counts as asking us to shut down too.
+                let shutdown = async {
+                    let _ = shutdown_rx.await;
+                };
+                runner.block_on(socket, shutdown)
+            })?;
 
-        let slowness_level = self.state.borrow().config.ticker_delay;
+        Ok(DetectorHandle {
+            local_addr: local_addr,
+            fd: fd,
+            shutdown_tx: Some(shutdown_tx),
+            thread: Some(thread),
+        })
+    }
 
-        let num_members_to_ping = state.borrow().config.num_members_to_ping as usize;
+    /// Run the failure detector forever. Errors sending or receiving gossip
+    /// are logged and otherwise ignored, so this only returns early if the
+    /// socket can't be set up.
+    pub fn run(&self) -> io::Result<()> {
+        self.run_until(future::pending())
+    }
 
-        let pinger = ticker.and_then(|_| {
+    /// Run the failure detector until `shutdown` resolves, at which point we
+    /// tell some of our peers that we are leaving the cluster so that they
+    /// don't take us for dead. This blocks the calling thread, see
+    /// `serve_until` for running on a runtime of one's own.
+    pub fn run_until<F>(&self, shutdown: F) -> io::Result<()>
+    where
+        F: Future<Output = ()>,
+    {
+        let listen_addr = self.state.lock().unwrap().config.addr;
+        let socket = net::UdpSocket::bind(&listen_addr)?;
+        self.block_on(socket, shutdown)
+    }
 
-            // Pick up to k random peers
-            let mut rng = thread_rng();
-            let nmembers = state.borrow().members.len();
-            let k = cmp::min(num_members_to_ping, nmembers);
+    /// Like `run_until`, but as a future to be run on the caller's tokio
+    /// runtime, multi-threaded or not.
+    pub async fn serve_until<F>(&self, shutdown: F) -> io::Result<()>
+    where
+        F: Future<Output = ()>,
+    {
+        let listen_addr = self.state.lock().unwrap().config.addr;
+        let socket = UdpSocket::bind(listen_addr).await?;
+        self.serve(socket, shutdown).await
+    }
 
-            let ping_addrs = seq::sample_iter(&mut rng, state.borrow().members.values(), k)
-                .map(|values| {
-                    values
-                        .iter()
-                        .map(|v| member_addr(v.get_member_ref()))
-                        .collect::<Vec<_>>()
-                })
-                .unwrap_or(vec![]);
+    /// Serve on `socket` using a single-threaded runtime of our own.
+    fn block_on<F>(&self, socket: net::UdpSocket, shutdown: F) -> io::Result<()>
+    where
+        F: Future<Output = ()>,
+    {
+        let runtime = runtime::Builder::new_current_thread().enable_all().build()?;
+        runtime.block_on(async {
+            socket.set_nonblocking(true)?;
+            let socket = UdpSocket::from_std(socket)?;
+            self.serve(socket, shutdown).await
+        })
+    }
 
-            // And signal for them to be pinged with a Syn ping. Note we just
-            // return the (peer_addr, gossip) pairs letting the downstream
-            // take care of actually sending the pings.
-            let cur_heartbeat = state.borrow().cur_heartbeat();
-            let pings = ping_addrs
-                .into_iter()
-                .map(|addr| {
-                    let gossip = make_gossip(
-                        cur_heartbeat,
-                        state.borrow().members.values().map(|m| {
-                            m.get_member_ref().clone()
-                        }),
-                        GossipType::Syn,
-                    );
-                    (addr, gossip)
-                })
-                .collect::<Vec<_>>();
+    /// The detector proper. Three things can happen: a ping interval passes
+    /// (the pinger), a datagram arrives (the ping listener), or we are asked
+    /// to shut down (the leaver). Whichever happens first is turned into an
+    /// `FDEvent` telling us what, if anything, to send out in response.
+    async fn serve<F>(&self, socket: UdpSocket, shutdown: F) -> io::Result<()>
+    where
+        F: Future<Output = ()>,
+    {
+        let dur = self.state.lock().unwrap().config.ping_interval;
+        // Like the pings themselves, the first tick is one interval away.
+        let mut ticker = interval_at((Instant::now() + dur).into(), dur);
 
-            // Update heartbeat
-            state.borrow_mut().epoch();
+        let slowness_level = self.state.lock().unwrap().config.ticker_delay;
+        // When the put off ping-out is due, if any.
+        let mut delayed_ping_out: Option<Instant> = None;
 
-            // Print crap
-            self.log_suspicisions();
+        let num_members_to_ping = self.state.lock().unwrap().config.num_members_to_ping as usize;
 
-            Ok(PingOut(pings))
-        });
+        let local_addr = socket.local_addr()?;
+        let mut codec = GossipCodec;
+        let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+        tokio::pin!(shutdown);
 
-        let ping_listener = stream.and_then(|(addr_from, gossip)| {
-            // 1. Merge the incoming membership state with our state.
-            state.borrow_mut().merge(addr_from, gossip.clone());
-
-            // 2. Then send an Ack ping with our updated membership list only if
-            // the incoming gossip is a Syn. If the ping was an Ack, this means
-            // we previously pinged that peer with a Syn and are just receiving
-            // their merged membership list, which we merged again above.
-
-            // Note that we don't actually send the ping here, but just
-            // return a future (that resolves immediately, since Result<T,U>
-            // is a type for which the Future trait is implemented.
-
-            let cur_heartbeat = state.borrow().cur_heartbeat();
-            match GossipType::from_u32(gossip.get_kind()) {
-                Some(GossipType::Syn) => {
-                    let gossip = make_gossip(
-                        cur_heartbeat,
-                        state.borrow().members.values().map(|m| {
-                            m.get_member_ref().clone()
-                        }),
-                        GossipType::Ack,
-                    );
-                    Ok(AckOut(addr_from, gossip))
+        loop {
+            let next_probe_deadline = self.state.lock().unwrap().next_probe_deadline();
+            let evt = tokio::select! {
+                scheduled = ticker.tick() => {
+                    let lag = Instant::now().saturating_duration_since(scheduled.into_std());
+                    self.state.lock().unwrap().lagged(lag);
+                    self.tick(slowness_level, &mut delayed_ping_out, num_members_to_ping)
                 }
-                Some(GossipType::Ack) => Ok(StateUpdated),
-                None => Ok(Unexpected(
-                    "Expected a gossip type, but got none!".to_string(),
-                )),
-            }
-        });
+                _ = sleep_until(delayed_ping_out.unwrap_or_else(Instant::now).into()),
+                    if delayed_ping_out.is_some() => {
+                    delayed_ping_out = None;
+                    self.ping_out(num_members_to_ping)
+                }
+                _ = sleep_until(next_probe_deadline.unwrap_or_else(Instant::now).into()),
+                    if next_probe_deadline.is_some() => self.probe_timeouts(),
+                received = socket.recv_from(&mut buf) => match received {
+                    Ok((len, addr_from)) => match codec.decode(&addr_from, &buf[..len]) {
+                        Ok((addr_from, gossip)) => self.handle_gossip(addr_from, gossip),
+                        Err(e) => self.drop_datagram(addr_from, e),
+                    },
+                    // Some platforms report an ICMP port unreachable from a
+                    // peer we pinged as an error on the next receive. A dead
+                    // peer is what we are here to notice, not a reason to stop.
+                    Err(e) => Unexpected(format!("could not receive gossip: {}", e)),
+                },
+                _ = &mut shutdown => self.leave_out(num_members_to_ping),
+            };
+            let handling_started = Instant::now();
 
-        let fut = pinger.select(ping_listener) // Pick events from any of the two streams, as they happen.
-            // We produce events in the resulting stream only if want to
-            // send stuff out. This happens if we are supposed to ping our
-            // peers, or if we received a ping and are now supposed to 
-            // send an ack ping to the peer. These events are encoded
-            // as the variants of the FDEvent enum, which we consider
-            // one by one below. The resulting stream yields (addr, gossip)
-            // tuples (notice the flatten()), which are then just forwarded
-            // to a sink wrapping over a UDP socket we previously bound to.
-            // The GossipCodec type handles the encoding of (addr, gossip)
-            // pairs into bytes and the other way around.
-            .filter_map(|evt| match evt {
+            // We only have work to do here if we are supposed to ping our
+            // peers, or if we received a ping and are now supposed to send an
+            // ack ping to the peer, or if we are leaving.
+            let (outgoing, stop) = match evt {
                 AckOut(addr, gossip) => {
                     info!("Going to ack ping from {:?}, gossip kind {}", &addr,
                           gossip.get_kind());
-                    Some(stream::iter_ok::<_, io::Error>(
-                            vec![(addr, gossip)].into_iter()))
+                    (vec![(addr, gossip)], false)
                 }
                 PingOut(ping_outs) => {
                     info!(
                         "going to ping {} member{} now (total {})",
                         ping_outs.len(),
                         if ping_outs.len() == 1 { "" } else { "s" },
-                        state.borrow().members.len()
+                        self.state.lock().unwrap().members.len()
                     );
-                    Some(stream::iter_ok::<_, io::Error>(ping_outs.into_iter()))
+                    (ping_outs, false)
+                }
+                PingDelayed(delay) => {
+                    debug!("putting off the ping-out by {:?}", delay);
+                    (vec![], false)
+                }
+                PingPending => {
+                    debug!("the ping-out is still put off, skipping this tick");
+                    (vec![], false)
                 }
-                FDEvent::StateUpdated => {
+                ProbeOut(probe_outs) => {
+                    info!("sending {} indirect probe message{}",
+                          probe_outs.len(),
+                          if probe_outs.len() == 1 { "" } else { "s" });
+                    (probe_outs, false)
+                }
+                LeaveOut(leave_outs) => {
+                    info!("leaving the cluster, telling {} member{}",
+                          leave_outs.len(),
+                          if leave_outs.len() == 1 { "" } else { "s" });
+                    (leave_outs, true)
+                }
+                StateUpdated => {
                     info!("state updated");
-                    None
+                    (vec![], false)
                 }
                 Unexpected(msg) => {
                     warn!("Something unexpected happened: {}", msg);
-                    None
+                    (vec![], false)
                 }
-            })
-            // Till here, we have a stream yielding *iterators*, so flatten
-            // them to the actual (addr, gossip) tuples.
-            .flatten()
-            .forward(sink);
-        // All the above drama exists as a computation graph, condensed in the
-        // form of a single future that should be driven to completion (which
-        // is indefinite for our case, we achieve our goals as a side effect
-        // of pursuing the completion of this future.
-        core.run(fut).unwrap();
+            };
+
+            for out in outgoing {
+                let mut datagram = Vec::new();
+                let addr = sendable_addr(codec.encode(out, &mut datagram), local_addr);
+                // A peer we can't reach from here (say, an IPv6 one when we
+                // are on IPv4) is no reason to stop talking to the others.
+                if let Err(e) = socket.send_to(&datagram, addr).await {
+                    warn!("could not send gossip to {}: {}", addr, e);
+                }
+            }
+
+            // Datagrams queue up while we are busy here.
+            self.state.lock().unwrap().lagged(handling_started.elapsed());
+
+            // Our farewells are out, so we are done.
+            if stop {
+                return Ok(());
+            }
+        }
+    }
+
+    /// What a tick of the ping interval calls for. With a `ticker_delay`, the
+    /// ping-out is put off until `delayed_ping_out`, and a ping-out still put
+    /// off from an earlier tick takes the place of this one.
+    fn tick(
+        &self,
+        ticker_delay: Option<u8>,
+        delayed_ping_out: &mut Option<Instant>,
+        k: usize,
+    ) -> FDEvent {
+        match ticker_delay {
+            Some(_) if delayed_ping_out.is_some() => PingPending,
+            Some(max_secs) => {
+                let delay = random_delay(max_secs);
+                *delayed_ping_out = Some(Instant::now() + delay);
+                PingDelayed(delay)
+            }
+            None => self.ping_out(k),
+        }
+    }
+
+    /// Pick up to k random peers, and signal for them to be pinged with a Syn
+    /// ping. Note we just return the (peer_addr, gossip) pairs letting the
+    /// caller take care of actually sending the pings.
+    fn ping_out(&self, k: usize) -> FDEvent {
+        let pings = {
+            let mut state = self.state.lock().unwrap();
+            let gossip = state.gossip(GossipType::Syn);
+            let peers = state.random_peers(k, |_| true);
+
+            let now = Instant::now();
+            state.start_probes(peers.iter().map(|(id, _)| id.clone()), now);
+            let pings = peers
+                .into_iter()
+                .map(|(_, addr)| (addr, gossip.clone()))
+                .collect::<Vec<_>>();
+
+            // Update heartbeat
+            state.epoch();
+
+            state.update_statuses(now);
+            state.reap(now);
+            pings
+        };
+
+        // Print crap
+        self.log_suspicisions();
+
+        PingOut(pings)
+    }
+
+    /// Ask around about the members that didn't answer our pings in time.
+    fn probe_timeouts(&self) -> FDEvent {
+        ProbeOut(self.state.lock().unwrap().expire_probes(Instant::now()))
+    }
+
+    fn handle_gossip(&self, addr_from: SocketAddr, gossip: Gossip) -> FDEvent {
+        let mut state = self.state.lock().unwrap();
+
+        // 1. Merge the incoming membership state with our state.
+        if !state.merge(addr_from, gossip.clone()) {
+            return Unexpected(format!(
+                "ignoring gossip from {:?}, which claims to be from elsewhere",
+                &addr_from
+            ));
+        }
+
+        // 2. Then send an Ack ping with our updated membership list only if
+        // the incoming gossip is a Syn. If the ping was an Ack, this means
+        // we previously pinged that peer with a Syn and are just receiving
+        // their merged membership list, which we merged again above.
+        match GossipType::from_u32(gossip.get_kind()) {
+            Some(GossipType::Syn) => AckOut(addr_from, state.gossip(GossipType::Ack)),
+            // The Ack may be to a ping we sent on someone else's behalf.
+            Some(GossipType::Ack) => {
+                let sender = member_addr(&sender_member(addr_from, &gossip));
+                let acks = state.relay_acks(sender);
+                if acks.is_empty() { StateUpdated } else { ProbeOut(acks) }
+            }
+            Some(GossipType::PingReq) => {
+                match state.relay_ping_req(addr_from, &gossip, Instant::now()) {
+                    Some(ping) => ProbeOut(vec![ping]),
+                    None => StateUpdated,
+                }
+            }
+            Some(GossipType::IndirectAck) => {
+                state.merge_indirect_ack(&gossip);
+                StateUpdated
+            }
+            Some(GossipType::Leave) => {
+                info!("{:?} is leaving the cluster", &addr_from);
+                StateUpdated
+            }
+            // Some newer version of us, most likely. What it told us about
+            // the cluster is merged all the same, but we have no idea what
+            // it wants in return.
+            None => {
+                debug!("ignoring gossip of unknown kind {} from {:?}", gossip.get_kind(),
+                       &addr_from);
+                StateUpdated
+            }
+        }
+    }
+
+    /// Anyone can send us anything, be it an older version of us, or some
+    /// stray packet. We count and ignore what we can't make sense of.
+    fn drop_datagram(&self, addr_from: SocketAddr, err: io::Error) -> FDEvent {
+        self.state.lock().unwrap().decode_failures += 1;
+        Unexpected(format!("dropping datagram from {:?}: {}", &addr_from, err))
+    }
+
+    /// Say goodbye to up to k random peers. The farewells get gossiped on to
+    /// the rest of the cluster from there. Only peers that advertise
+    /// `CAP_LEAVE` are told, since older ones would take the Leave for a
+    /// heartbeat and think all the better of us.
+    fn leave_out(&self, k: usize) -> FDEvent {
+        let state = self.state.lock().unwrap();
+        let gossip = state.gossip(GossipType::Leave);
+        let leaves = state
+            .random_peers(k, |m| m.supports(CAP_LEAVE))
+            .into_iter()
+            .map(|(_, addr)| (addr, gossip.clone()))
+            .collect::<Vec<_>>();
+        LeaveOut(leaves)
     }
 }
 
-pub struct GossipCodec;
+/// A failure detector running on a thread of its own, see `PhiFD::start`.
+/// Dropping the handle asks the detector to shut down, but doesn't wait for
+/// it to do so.
+pub struct DetectorHandle {
+    local_addr: SocketAddr,
+    fd: PhiFD,
+    shutdown_tx: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl DetectorHandle {
+    /// The address the detector is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// A stream of membership events from now on.
+    pub fn subscribe(&self) -> Receiver<MembershipEvent> {
+        self.fd.subscribe()
+    }
+
+    /// What the detector currently knows about every member.
+    pub fn members(&self) -> Vec<MemberSnapshot> {
+        self.fd.members()
+    }
+
+    /// The detector itself, for anything else one may want to ask it.
+    pub fn detector(&self) -> &PhiFD {
+        &self.fd
+    }
 
-impl UdpCodec for GossipCodec {
-    type In = (SocketAddr, Gossip);
-    type Out = (SocketAddr, Gossip);
+    /// Ask the detector to leave the cluster and stop, and wait for it to do
+    /// so.
+    pub fn shutdown(mut self) -> io::Result<()> {
+        if let Some(tx) = self.shutdown_tx.take() {
+            // The detector may have already stopped on its own, in which case
+            // there is no one to tell.
+            let _ = tx.send(());
+        }
+        self.join_thread()
+    }
 
-    fn decode(&mut self, src: &SocketAddr, buf: &[u8]) -> io::Result<Self::In> {
+    /// Wait for the detector to stop without asking it to. Unless it is shut
+    /// down some other way, this only returns when the detector fails.
+    pub fn join(mut self) -> io::Result<()> {
+        self.join_thread()
+    }
+
+    fn join_thread(&mut self) -> io::Result<()> {
+        match self.thread.take() {
+            Some(thread) => thread.join().unwrap_or_else(|_| {
+                Err(io::Error::new(io::ErrorKind::Other, "failure detector thread panicked"))
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Big enough for any UDP datagram.
+const MAX_DATAGRAM_SIZE: usize = 65536;
+
+/// For how many ping intervals a member that changed gets precedence in the
+/// gossip we send, see `FDState::fill_members`.
+const RECENT_CHANGE_ROUNDS: u32 = 10;
+
+/// The least room a member takes up in gossip: its field tag and length,
+/// then its ip, port, suspicion and heartbeat at their smallest.
+const MIN_MEMBER_RECORD_SIZE: usize = 2 + 2 + 2 + 9 + 2;
+
+/// Turns datagrams into `(sender, gossip)` pairs and back.
+pub struct GossipCodec;
+
+impl GossipCodec {
+    pub fn decode(&mut self, src: &SocketAddr, buf: &[u8]) -> io::Result<(SocketAddr, Gossip)> {
         info!("datagram received from {:?}", src);
-        // TODO: this crashes when an old version of the FD pings us.
-        let gossip = parse_from_bytes::<Gossip>(buf).unwrap();
+        let gossip = parse_from_bytes::<Gossip>(buf).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, e)
+        })?;
         Ok((*src, gossip))
     }
 
-    fn encode(&mut self, (addr, msg): Self::Out, buf: &mut Vec<u8>) -> SocketAddr {
+    pub fn encode(&mut self, (addr, msg): (SocketAddr, Gossip), buf: &mut Vec<u8>) -> SocketAddr {
         msg.write_to_vec(buf).expect(
             "an error occurred writing to the udp socket",
         );
         addr
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use tokio::sync::broadcast;
+
+    fn peer_gossip(heartbeat: u64, members: Vec<Member>) -> Gossip {
+        make_gossip(1, heartbeat, members.into_iter(), GossipType::Syn)
+    }
+
+    #[test]
+    fn test_dead_members_are_reaped_and_not_resurrected_by_stale_gossip() {
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:12345".parse().unwrap());
+        config.set_suspect_timeout(Duration::from_secs(1));
+        config.set_reap_timeout(Duration::from_secs(1));
+        config.set_tombstone_grace_period(Duration::from_secs(60));
+        let mut state = FDState::new(Some(config));
+
+        let peer: SocketAddr = "127.0.0.1:20000".parse().unwrap();
+        let mut dead = member_from_address("127.0.0.1:30000").unwrap();
+        dead.set_heartbeat(7);
+        let dead_id = MemberID::of(&dead);
+        state.merge(peer, peer_gossip(1, vec![dead.clone()]));
+        assert!(state.members.contains_key(&dead_id));
+
+        // Nothing from the member for a long while: suspect, then dead, then
+        // reaped.
+        let mut now = Instant::now() + Duration::from_secs(60);
+        for _ in 0..3 {
+            state.update_statuses(now);
+            state.reap(now);
+            now += Duration::from_secs(1);
+        }
+        assert!(!state.members.contains_key(&dead_id));
+
+        // A lagging peer still gossips the old heartbeat.
+        state.merge(peer, peer_gossip(2, vec![dead.clone()]));
+        assert!(!state.members.contains_key(&dead_id));
+
+        // But a newer heartbeat means it's back.
+        dead.set_heartbeat(8);
+        state.merge(peer, peer_gossip(3, vec![dead.clone()]));
+        assert!(state.members.contains_key(&dead_id));
+        assert!(state.tombstones.is_empty());
+    }
+
+    #[test]
+    fn test_leave_gossip_marks_the_sender_as_left() {
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:12345".parse().unwrap());
+        let mut state = FDState::new(Some(config));
+
+        let peer: SocketAddr = "127.0.0.1:20000".parse().unwrap();
+        let peer_id = MemberID::Addr(peer);
+        state.merge(peer, peer_gossip(1, vec![]));
+        assert_eq!(state.members[&peer_id].status(), MemberStatus::Alive);
+
+        let leave = make_gossip(1, 2, vec![].into_iter(), GossipType::Leave);
+        state.merge(peer, leave);
+        assert_eq!(state.members[&peer_id].status(), MemberStatus::Left);
+        assert!(state.random_peers(3, |_| true).is_empty());
+    }
+
+    #[test]
+    fn test_leave_is_only_sent_to_peers_that_understand_it() {
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:12345".parse().unwrap());
+        let fd = PhiFD::new(Some(config));
+        let new_peer: SocketAddr = "127.0.0.1:20001".parse().unwrap();
+        let old_peer: SocketAddr = "127.0.0.1:20002".parse().unwrap();
+        fd.handle_gossip(new_peer, gossip_from("a", "127.0.0.1:20001", 1, GossipType::Syn));
+        let mut gossip = gossip_from("b", "127.0.0.1:20002", 1, GossipType::Syn);
+        gossip.clear_capabilities();
+        fd.handle_gossip(old_peer, gossip);
+        assert_eq!(fd.members().len(), 2);
+
+        match fd.leave_out(3) {
+            LeaveOut(leaves) => {
+                assert_eq!(leaves.len(), 1);
+                assert_eq!(leaves[0].0, new_peer);
+                assert_eq!(leaves[0].1.get_kind(), GossipType::Leave.into());
+            }
+            _ => panic!("expected leave gossip"),
+        }
+    }
+
+    #[test]
+    fn test_unknown_gossip_kinds_are_merged_but_not_answered() {
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:0".parse().unwrap());
+        let fd = PhiFD::new(Some(config));
+
+        let peer: SocketAddr = "127.0.0.1:20000".parse().unwrap();
+        let mut gossip = peer_gossip(1, vec![]);
+        gossip.set_kind(42);
+        gossip.set_version(PROTOCOL_VERSION + 1);
+        gossip.set_capabilities(CAPABILITIES | 1 << 63);
+        match fd.handle_gossip(peer, gossip) {
+            StateUpdated => {}
+            _ => panic!("expected no reply to an unknown gossip kind"),
+        }
+
+        let members = fd.members();
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].version, PROTOCOL_VERSION + 1);
+        assert!(fd.state.lock().unwrap().members[&members[0].id].supports(CAP_LEAVE));
+    }
+
+    #[test]
+    fn test_gossip_from_peers_predating_versioning() {
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:0".parse().unwrap());
+        let fd = PhiFD::new(Some(config));
+
+        let peer: SocketAddr = "127.0.0.1:20000".parse().unwrap();
+        let mut gossip = peer_gossip(1, vec![]);
+        gossip.clear_version();
+        gossip.clear_capabilities();
+        match fd.handle_gossip(peer, gossip) {
+            AckOut(addr, _) => assert_eq!(addr, peer),
+            _ => panic!("expected an ack"),
+        }
+
+        let members = fd.members();
+        assert_eq!(members[0].version, 0);
+        assert!(!fd.state.lock().unwrap().members[&members[0].id].supports(CAP_LEAVE));
+    }
+
+    #[test]
+    fn test_ipv6_members() {
+        let mut config = Config::default();
+        config.set_addr("[::1]:12345".parse().unwrap());
+        let mut state = FDState::new(Some(config));
+
+        let peer: SocketAddr = "[2001:db8::1]:20000".parse().unwrap();
+        let other = member_from_address("[2001:db8::2]:20000").unwrap();
+        assert_eq!(other.get_ip(), 0);
+        assert_eq!(member_addr(&other), "[2001:db8::2]:20000".parse().unwrap());
+        let other_id = MemberID::of(&other);
+        // Hearing of ourselves doesn't make us a member.
+        let us = member_from_address("[::1]:12345").unwrap();
+        state.merge(peer, peer_gossip(1, vec![other, us]));
+
+        let mut ids = state.members.keys().cloned().collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, vec![MemberID::Addr(peer), other_id]);
+
+        // IPv4 peers talking to a dual-stack socket are known by their IPv4
+        // address.
+        let mapped: SocketAddr = "[::ffff:127.0.0.1]:30000".parse().unwrap();
+        state.merge(mapped, peer_gossip(1, vec![]));
+        let v4: SocketAddr = "127.0.0.1:30000".parse().unwrap();
+        assert!(state.members.contains_key(&MemberID::Addr(v4)));
+        assert_eq!(sendable_addr(v4, state.config.addr), mapped);
+    }
+
+    #[test]
+    fn test_default_node_id_is_stable() {
+        // Without an ID, we go by the address we advertise, which a restart
+        // in place doesn't change.
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:12345".parse().unwrap());
+        assert_eq!(config.node_id(), "127.0.0.1:12345");
+        config.set_advertise_addr("192.0.2.1:4000".parse().unwrap());
+        assert_eq!(config.node_id(), "192.0.2.1:4000");
+        assert_eq!(FDState::new(Some(config)).node_id, "192.0.2.1:4000");
+
+        // Random IDs are there for the asking, or when there is no address to
+        // go by.
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:12345".parse().unwrap());
+        config.set_random_node_id(true);
+        assert_ne!(config.node_id(), config.node_id());
+        let mut config = Config::default();
+        assert_ne!(config.node_id(), config.node_id());
+        config.set_node_id("us".to_string());
+        assert_eq!(config.node_id(), "us");
+    }
+
+    #[test]
+    fn test_members_are_known_by_node_id() {
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:12345".parse().unwrap());
+        config.set_node_id("us".to_string());
+        let seed = member_from_address("127.0.0.1:20000").unwrap();
+        let mut state = FDState::with_members(vec![seed], Some(config));
+        let seed_id = MemberID::Addr("127.0.0.1:20000".parse().unwrap());
+        assert!(state.members.contains_key(&seed_id));
+
+        // Once the seed tells us who it is, we know it by that.
+        let peer: SocketAddr = "127.0.0.1:20000".parse().unwrap();
+        let mut gossip = peer_gossip(1, vec![]);
+        gossip.set_node_id("a".to_string());
+        state.merge(peer, gossip);
+        let a = MemberID::Node("a".to_string());
+        assert_eq!(state.members.keys().collect::<Vec<_>>(), vec![&a]);
+
+        // The same node on a new address is still the same member.
+        let moved: SocketAddr = "127.0.0.2:20000".parse().unwrap();
+        let mut gossip = peer_gossip(2, vec![]);
+        gossip.set_node_id("a".to_string());
+        state.merge(moved, gossip);
+        assert_eq!(state.members.len(), 1);
+        assert_eq!(state.members[&a].get_addr(), moved);
+
+        // While another node on the old address is another member.
+        let mut gossip = peer_gossip(1, vec![]);
+        gossip.set_node_id("b".to_string());
+        state.merge(peer, gossip);
+        assert_eq!(state.members.len(), 2);
+        assert_eq!(state.members[&MemberID::Node("b".to_string())].get_addr(), peer);
+
+        // Gossip about us, even on another address, is not about a member.
+        let mut us = member_from_address("10.0.0.1:12345").unwrap();
+        us.set_node_id("us".to_string());
+        state.merge(peer, peer_gossip(2, vec![us]));
+        assert_eq!(state.members.len(), 2);
+        assert_eq!(state.gossip(GossipType::Syn).get_node_id(), "us");
+    }
+
+    #[test]
+    fn test_advertised_addresses() {
+        let mut config = Config::default();
+        config.set_addr("0.0.0.0:12345".parse().unwrap());
+        let mut state = FDState::new(Some(config));
+        // Nobody can reach us at 0.0.0.0.
+        assert!(!state.gossip(GossipType::Syn).has_advertise_addr());
+
+        state.config.set_advertise_addr("192.0.2.1:4000".parse().unwrap());
+        assert_eq!(state.gossip(GossipType::Syn).get_advertise_addr(), "192.0.2.1:4000");
+
+        // A peer behind a port mapping tells us where to reach it.
+        let source: SocketAddr = "172.17.0.1:32768".parse().unwrap();
+        let mut gossip = peer_gossip(1, vec![]);
+        gossip.set_advertise_addr("192.0.2.2:4000".to_string());
+        state.merge(source, gossip);
+        let advertised: SocketAddr = "192.0.2.2:4000".parse().unwrap();
+        assert!(state.members.contains_key(&MemberID::Addr(advertised)));
+
+        // Hearing of our advertised address is hearing of ourselves.
+        let us = member_from_address("192.0.2.1:4000").unwrap();
+        let mut gossip = peer_gossip(2, vec![us]);
+        gossip.set_advertise_addr("192.0.2.2:4000".to_string());
+        state.merge(source, gossip);
+        assert_eq!(state.members.len(), 1);
+    }
+
+    #[test]
+    fn test_sender_records() {
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:12345".parse().unwrap());
+        config.set_node_id("us".to_string());
+        // We were given our own address as a seed.
+        let seed = member_from_address("127.0.0.1:12345").unwrap();
+        let mut state = FDState::with_members(vec![seed], Some(config));
+
+        let ours = state.gossip(GossipType::Syn);
+        assert_eq!(ours.get_sender().get_node_id(), "us");
+        assert_eq!(member_addr(ours.get_sender()), state.config.addr);
+
+        // Pinging the seed gets our own gossip back, so we forget it.
+        let addr = state.config.addr;
+        assert!(state.merge(addr, ours));
+        assert!(state.members.is_empty());
+
+        // A multi-homed peer's gossip may come from an address other than
+        // the one it wants to be reached at; we go by what it says.
+        let mut sender = member_from_address("192.0.2.1:4000").unwrap();
+        sender.set_node_id("a".to_string());
+        sender.set_heartbeat(3);
+        let mut gossip = peer_gossip(1, vec![]);
+        gossip.set_sender(sender.clone());
+        let source: SocketAddr = "10.0.0.1:999".parse().unwrap();
+        assert!(state.merge(source, gossip.clone()));
+        let a = MemberID::Node("a".to_string());
+        assert_eq!(state.members[&a].get_addr(), member_addr(&sender));
+
+        // Unless we are asked to check the source address.
+        state.config.set_verify_sender_addr(true);
+        sender.set_node_id("b".to_string());
+        gossip.set_sender(sender);
+        assert!(!state.merge(source, gossip));
+        assert_eq!(state.members.len(), 1);
+
+        // A record that doesn't say where to reach the sender is filled in
+        // with where the gossip came from.
+        let mut sender = member_from_address("0.0.0.0:0").unwrap();
+        sender.set_node_id("c".to_string());
+        let mut gossip = peer_gossip(1, vec![]);
+        gossip.set_sender(sender);
+        assert!(state.merge(source, gossip));
+        let c = MemberID::Node("c".to_string());
+        assert_eq!(state.members[&c].get_addr(), source);
+    }
+
+    /// Gossip of the given kind from the node `node_id` at `addr`.
+    fn gossip_from(node_id: &str, addr: &str, heartbeat: u64, typ: GossipType) -> Gossip {
+        let mut sender = member_from_address(addr).unwrap();
+        sender.set_node_id(node_id.to_string());
+        sender.set_heartbeat(heartbeat);
+        let mut gossip = make_gossip(1, heartbeat, vec![].into_iter(), typ);
+        gossip.set_sender(sender);
+        gossip
+    }
+
+    #[test]
+    fn test_indirect_probes() {
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:12345".parse().unwrap());
+        config.set_probe_timeout(Duration::from_millis(500));
+        let fd = PhiFD::new(Some(config));
+        let target: SocketAddr = "127.0.0.1:20001".parse().unwrap();
+        let helper: SocketAddr = "127.0.0.1:20002".parse().unwrap();
+        let old: SocketAddr = "127.0.0.1:20003".parse().unwrap();
+        let t = MemberID::Node("t".to_string());
+
+        let mut state = fd.state.lock().unwrap();
+        state.merge(target, gossip_from("t", "127.0.0.1:20001", 1, GossipType::Ack));
+        state.merge(helper, gossip_from("h", "127.0.0.1:20002", 1, GossipType::Ack));
+        let mut gossip = gossip_from("o", "127.0.0.1:20003", 1, GossipType::Ack);
+        gossip.clear_capabilities();
+        state.merge(old, gossip);
+
+        // The target doesn't answer our ping in time, so we ask the one
+        // member that knows how to probe it for us.
+        let now = Instant::now();
+        state.start_probes(vec![t.clone()].into_iter(), now);
+        assert!(state.expire_probes(now).is_empty());
+        assert_eq!(state.next_probe_deadline(), Some(now + Duration::from_millis(500)));
+        let ping_reqs = state.expire_probes(now + Duration::from_millis(500));
+        assert_eq!(ping_reqs.len(), 1);
+        let (addr, ping_req) = ping_reqs[0].clone();
+        assert_eq!(addr, helper);
+        assert_eq!(GossipType::from_u32(ping_req.get_kind()), Some(GossipType::PingReq));
+        assert_eq!(MemberID::of(ping_req.get_target()), t);
+        drop(state);
+
+        // The helper got through to it, which is as good as hearing from it.
+        let mut ack = gossip_from("h", "127.0.0.1:20002", 2, GossipType::IndirectAck);
+        let mut news = member_from_address("127.0.0.1:20001").unwrap();
+        news.set_node_id("t".to_string());
+        news.set_heartbeat(5);
+        ack.set_target(news);
+        fd.handle_gossip(helper, ack);
+        let state = fd.state.lock().unwrap();
+        assert_eq!(state.members[&t].get_member_ref().get_heartbeat(), 5);
+        assert!(state.probes.is_empty());
+    }
+
+    #[test]
+    fn test_local_health_dampens_suspicion() {
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:12345".parse().unwrap());
+        config.set_probe_timeout(Duration::from_millis(500));
+        config.set_suspicion_threshold(8f64);
+        config.set_suspect_timeout(Duration::from_secs(1));
+        config.set_lag_tolerance(Duration::from_millis(200));
+        let mut state = FDState::new(Some(config));
+        let peer: SocketAddr = "127.0.0.1:20001".parse().unwrap();
+        state.merge(peer, gossip_from("p", "127.0.0.1:20001", 1, GossipType::Ack));
+        let p = MemberID::Node("p".to_string());
+
+        // Missing an Ack and running late both count against us.
+        let now = Instant::now();
+        state.start_probes(vec![p.clone()].into_iter(), now);
+        state.expire_probes(now + Duration::from_millis(500));
+        state.lagged(Duration::from_millis(100));
+        assert_eq!(state.health.score(), 1);
+        state.lagged(Duration::from_secs(2));
+        assert_eq!(state.health.score(), 2);
+        assert_eq!(state.probe_timeout(), Duration::from_millis(1500));
+
+        // So we let a member be silent for as much longer as we wait on
+        // probes before we suspect it, and tell others as much.
+        let mut at = Instant::now();
+        while state.members[&p].phi(at).map(|phi| phi < 9f64).unwrap_or(true) {
+            at += Duration::from_millis(100);
+        }
+        state.update_statuses(at);
+        assert_eq!(state.members[&p].status(), MemberStatus::Alive);
+        assert!(state.members[&p].gossip_record(at, state.extra_pause()).get_suspicion() < 8f64);
+        at += Duration::from_secs(1);
+        state.update_statuses(at);
+        assert_eq!(state.members[&p].status(), MemberStatus::Suspect);
+
+        // And we give a suspect three times as long before declaring it dead.
+        state.update_statuses(at + Duration::from_secs(2));
+        assert_eq!(state.members[&p].status(), MemberStatus::Suspect);
+        state.update_statuses(at + Duration::from_secs(3));
+        assert_eq!(state.members[&p].status(), MemberStatus::Dead);
+
+        // Each answer restores some of our confidence.
+        state.probes.clear();
+        state.start_probes(vec![p.clone()].into_iter(), now);
+        state.merge(peer, gossip_from("p", "127.0.0.1:20001", 2, GossipType::Ack));
+        assert_eq!(state.health.score(), 1);
+    }
+
+    #[test]
+    fn test_unhealthy_nodes_still_suspect_the_obviously_dead() {
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:12345".parse().unwrap());
+        config.set_suspicion_threshold(8f64);
+        config.set_max_local_health_score(8);
+        let mut state = FDState::new(Some(config));
+        let peer: SocketAddr = "127.0.0.1:20001".parse().unwrap();
+        state.merge(peer, gossip_from("p", "127.0.0.1:20001", 1, GossipType::Ack));
+        let p = MemberID::Node("p".to_string());
+
+        for _ in 0..20 {
+            state.lagged(Duration::from_secs(2));
+        }
+        assert_eq!(state.health.score(), 8);
+
+        // We hold off for no longer than the extra pause.
+        let mut at = Instant::now();
+        while state.members[&p].phi(at).map(|phi| phi < 100f64).unwrap_or(true) {
+            at += Duration::from_millis(100);
+        }
+        state.update_statuses(at + state.extra_pause());
+        assert_eq!(state.members[&p].status(), MemberStatus::Suspect);
+    }
+
+    #[test]
+    fn test_missing_acks_from_suspects_leaves_health_alone() {
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:12345".parse().unwrap());
+        config.set_suspicion_threshold(8f64);
+        let mut state = FDState::new(Some(config));
+        let peer: SocketAddr = "127.0.0.1:20001".parse().unwrap();
+        state.merge(peer, gossip_from("p", "127.0.0.1:20001", 1, GossipType::Ack));
+        let p = MemberID::Node("p".to_string());
+
+        let mut at = Instant::now();
+        while state.members[&p].phi(at).map(|phi| phi < 9f64).unwrap_or(true) {
+            at += Duration::from_millis(100);
+        }
+        state.update_statuses(at);
+        assert_eq!(state.members[&p].status(), MemberStatus::Suspect);
+
+        state.start_probes(vec![p.clone()].into_iter(), at);
+        state.expire_probes(at + state.probe_timeout());
+        assert_eq!(state.health.score(), 0);
+    }
+
+    #[test]
+    fn test_relaying_ping_reqs() {
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:12345".parse().unwrap());
+        let fd = PhiFD::new(Some(config));
+        let target: SocketAddr = "127.0.0.1:20001".parse().unwrap();
+        let requesters: Vec<SocketAddr> = vec![
+            "127.0.0.1:20002".parse().unwrap(),
+            "127.0.0.1:20003".parse().unwrap(),
+        ];
+        let mut whom = member_from_address("127.0.0.1:20001").unwrap();
+        whom.set_node_id("t".to_string());
+
+        // We probe the target for whoever asks, but only once at a time.
+        for (i, &requester) in requesters.iter().enumerate() {
+            let mut ping_req = gossip_from(&format!("r{}", i), &requester.to_string(), 1,
+                                           GossipType::PingReq);
+            ping_req.set_target(whom.clone());
+            match fd.handle_gossip(requester, ping_req) {
+                ProbeOut(ref pings) if i == 0 => {
+                    assert_eq!(pings.len(), 1);
+                    assert_eq!(pings[0].0, target);
+                    assert_eq!(pings[0].1.get_kind(), GossipType::Syn.into());
+                }
+                StateUpdated if i == 1 => (),
+                _ => panic!("unexpected response to ping-req {}", i),
+            }
+        }
+
+        // Once it answers, everyone who asked hears of it.
+        let acks = match fd.handle_gossip(target, gossip_from("t", "127.0.0.1:20001", 9,
+                                                              GossipType::Ack)) {
+            ProbeOut(acks) => acks,
+            _ => panic!("no indirect acks"),
+        };
+        let mut to = acks.iter().map(|&(addr, _)| addr).collect::<Vec<_>>();
+        to.sort();
+        assert_eq!(to, requesters);
+        for (_, ack) in acks {
+            assert_eq!(GossipType::from_u32(ack.get_kind()), Some(GossipType::IndirectAck));
+            assert_eq!(ack.get_target().get_heartbeat(), 9);
+        }
+        assert!(fd.state.lock().unwrap().relays.is_empty());
+    }
+
+    #[test]
+    fn test_suspicion_is_gossiped() {
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:12345".parse().unwrap());
+        config.set_suspicion_threshold(8f64);
+        let mut state = FDState::new(Some(config));
+        let x = MemberID::Node("x".to_string());
+        let mut about_x = member_from_address("127.0.0.1:20000").unwrap();
+        about_x.set_node_id("x".to_string());
+        about_x.set_heartbeat(1);
+
+        // Two peers have an opinion of x, and one predates opinions.
+        for &(node_id, addr, phi) in &[("a", "127.0.0.1:20001", 9f64),
+                                       ("b", "127.0.0.1:20002", 1f64),
+                                       ("c", "127.0.0.1:20003", 0f64)] {
+            let mut gossip = gossip_from(node_id, addr, 1, GossipType::Syn);
+            about_x.set_suspicion(phi);
+            gossip.mut_members().push(about_x.clone());
+            if node_id == "c" {
+                gossip.clear_capabilities();
+            }
+            state.merge(addr.parse().unwrap(), gossip);
+        }
+
+        let now = Instant::now();
+        let snapshot = state.snapshot(now);
+        let x_snapshot = snapshot.iter().find(|m| m.id == x).unwrap();
+        assert_eq!(x_snapshot.cluster, Some(ClusterSuspicion {
+            reporters: 2,
+            suspecting: 1,
+            max_phi: 9f64,
+            median_phi: 5f64,
+        }));
+        // Nobody has an opinion of the peers themselves yet.
+        assert!(snapshot.iter().filter(|m| m.id != x).all(|m| m.cluster.is_none()));
+
+        // What we gossip of x is our own phi for it.
+        let later = now + Duration::from_secs(3);
+        let gossiped = state.members[&x].gossip_record(later, state.extra_pause());
+        assert_eq!(Some(gossiped.get_suspicion()), state.members[&x].phi(later));
+    }
+
+    #[test]
+    fn test_membership_events() {
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:12345".parse().unwrap());
+        config.set_suspect_timeout(Duration::from_secs(1));
+        let mut state = FDState::new(Some(config));
+        let mut events = state.events.subscribe();
+
+        let peer: SocketAddr = "127.0.0.1:20000".parse().unwrap();
+        let peer_id = MemberID::Addr(peer);
+        state.merge(peer, peer_gossip(1, vec![]));
+        let now = Instant::now() + Duration::from_secs(60);
+        state.update_statuses(now);
+        state.update_statuses(now + Duration::from_secs(1));
+        state.merge(peer, peer_gossip(2, vec![]));
+        state.merge(peer, make_gossip(1, 3, vec![].into_iter(), GossipType::Leave));
+
+        let mut kinds = vec![];
+        while let Ok(event) = events.try_recv() {
+            if event.kind != MembershipEventKind::PhiUpdated {
+                assert_eq!(event.id, peer_id);
+                kinds.push(event.kind);
+            }
+        }
+        assert_eq!(
+            kinds,
+            vec![
+                MembershipEventKind::MemberJoined,
+                MembershipEventKind::MemberSuspected,
+                MembershipEventKind::MemberFailed,
+                MembershipEventKind::MemberRecovered,
+                MembershipEventKind::MemberLeft,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_slow_subscribers_miss_events_rather_than_pile_them_up() {
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:12345".parse().unwrap());
+        config.set_event_capacity(4);
+        let mut state = FDState::new(Some(config));
+        let mut events = state.events.subscribe();
+        let forgotten = state.events.subscribe();
+        drop(forgotten);
+
+        for i in 0..10 {
+            let peer = SocketAddr::from(([127, 0, 0, 1], 20000 + i));
+            state.merge(peer, peer_gossip(1, vec![]));
+        }
+        match events.try_recv() {
+            Err(broadcast::error::TryRecvError::Lagged(missed)) => assert_eq!(missed, 6),
+            other => panic!("expected to have lagged, got {:?}", other),
+        }
+        let mut left = 0;
+        while let Ok(event) = events.try_recv() {
+            assert_eq!(event.kind, MembershipEventKind::MemberJoined);
+            left += 1;
+        }
+        assert_eq!(left, 4);
+    }
+
+    #[test]
+    fn test_start_and_shutdown() {
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:0".parse().unwrap());
+        config.set_ping_interval(Duration::from_millis(10));
+        let handle = PhiFD::start(vec![], Some(config)).unwrap();
+        assert!(handle.local_addr().port() != 0);
+        handle.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_handle_reports_members() {
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:0".parse().unwrap());
+        config.set_ping_interval(Duration::from_millis(10));
+        let seed = member_from_address("127.0.0.1:20000").unwrap();
+        let handle = PhiFD::start(vec![seed], Some(config)).unwrap();
+
+        let mut members = handle.members();
+        for _ in 0..100 {
+            if !members.is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+            members = handle.members();
+        }
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].addr, "127.0.0.1:20000".parse().unwrap());
+        assert_eq!(members[0].status, MemberStatus::Alive);
+        assert!(members[0].phi.is_some());
+        handle.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_detector_can_be_shared_between_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<PhiFD>();
+        assert_send_sync::<DetectorHandle>();
+
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:0".parse().unwrap());
+        let seed = member_from_address("127.0.0.1:20000").unwrap();
+        let fd = PhiFD::with_members(vec![seed], Some(config));
+        let other = fd.clone();
+        let members = thread::spawn(move || other.members()).join().unwrap();
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].id, fd.members()[0].id);
+    }
+
+    #[test]
+    fn test_serve_on_a_multi_threaded_runtime() {
+        let peer = net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        peer.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:0".parse().unwrap());
+        config.set_ping_interval(Duration::from_millis(10));
+        let seed = member_from_sockaddr(peer.local_addr().unwrap());
+        let fd = PhiFD::with_members(vec![seed], Some(config));
+
+        let runtime = runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let runner = fd.clone();
+        let task = runtime.spawn(async move {
+            runner.serve_until(async {
+                let _ = shutdown_rx.await;
+            }).await
+        });
+
+        // We get pinged, and can ask about members meanwhile.
+        let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+        let (len, _) = peer.recv_from(&mut buf).unwrap();
+        let gossip = parse_from_bytes::<Gossip>(&buf[..len]).unwrap();
+        assert_eq!(GossipType::from_u32(gossip.get_kind()), Some(GossipType::Syn));
+        assert_eq!(fd.members().len(), 1);
+
+        shutdown_tx.send(()).unwrap();
+        runtime.block_on(task).unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_gossip_fits_in_a_datagram() {
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:12345".parse().unwrap());
+        config.set_ping_interval(Duration::from_secs(1));
+        config.set_max_gossip_size(1400);
+        let members = (0..1000)
+            .map(|i| member_from_address(&format!("10.0.{}.{}:12345", i / 250, i % 250)).unwrap())
+            .collect::<Vec<_>>();
+        let mut state = FDState::with_members(members, Some(config));
+
+        // No member takes up less room than we allow for.
+        let smallest = member_from_address("0.0.0.0:0").unwrap();
+        assert_eq!(2 + smallest.compute_size() as usize, MIN_MEMBER_RECORD_SIZE);
+
+        let gossip = state.gossip(GossipType::Syn);
+        assert!(gossip.compute_size() <= 1400);
+        assert!(gossip.get_members().len() > 10);
+        assert!(gossip.get_members().len() < 1000);
+
+        // News of a member goes first.
+        let later = Instant::now() + Duration::from_secs(60);
+        let news = MemberID::Addr("10.0.3.7:12345".parse().unwrap());
+        state.members.get_mut(&news).unwrap()
+            .update_status(later, 0f64, Duration::from_secs(1), Duration::from_secs(0));
+        let mut gossip = state.bare_gossip(GossipType::Syn);
+        state.fill_members(&mut gossip, later);
+        assert_eq!(MemberID::of(&gossip.get_members()[0]), news);
+
+        // And everyone gets gossiped about sooner or later.
+        let mut gossiped = HashSet::new();
+        for _ in 0..500 {
+            for member in state.gossip(GossipType::Syn).get_members() {
+                gossiped.insert(MemberID::of(member));
+            }
+        }
+        assert_eq!(gossiped.len(), 1000);
+    }
+
+    #[test]
+    fn test_ticker_delay_puts_off_pings() {
+        for _ in 0..100 {
+            assert!(random_delay(1) <= Duration::from_secs(1));
+        }
+        assert_eq!(random_delay(0), Duration::from_secs(0));
+
+        let peer = net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        peer.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:0".parse().unwrap());
+        config.set_ping_interval(Duration::from_millis(10));
+        config.set_ticker_delay(1);
+        let seed = member_from_sockaddr(peer.local_addr().unwrap());
+        let handle = PhiFD::start(vec![seed], Some(config)).unwrap();
+
+        // Put off or not, the pings do go out.
+        let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+        let (len, _) = peer.recv_from(&mut buf).unwrap();
+        let gossip = parse_from_bytes::<Gossip>(&buf[..len]).unwrap();
+        assert_eq!(GossipType::from_u32(gossip.get_kind()), Some(GossipType::Syn));
+
+        // But nowhere near every 10ms, as the ticks in between are skipped.
+        let start = Instant::now();
+        let mut pings = 0;
+        peer.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+        while start.elapsed() < Duration::from_millis(500) {
+            if peer.recv_from(&mut buf).is_ok() {
+                pings += 1;
+            }
+        }
+        assert!(pings < 20, "{} pings in 500ms", pings);
+        handle.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_ticks_are_skipped_while_the_ping_out_is_put_off() {
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:12345".parse().unwrap());
+        let seed = member_from_address("127.0.0.1:20000").unwrap();
+        let fd = PhiFD::with_members(vec![seed], Some(config));
+
+        // Without a delay, every tick pings.
+        let mut delayed_ping_out = None;
+        match fd.tick(None, &mut delayed_ping_out, 3) {
+            PingOut(ref pings) if pings.len() == 1 => (),
+            _ => panic!("expected a ping-out"),
+        }
+        assert!(delayed_ping_out.is_none());
+
+        // With one, the first tick puts the ping-out off, and the ones after
+        // that don't ping at all until it is due.
+        let before = Instant::now();
+        let delay = match fd.tick(Some(1), &mut delayed_ping_out, 3) {
+            PingDelayed(delay) => delay,
+            _ => panic!("expected the ping-out to be put off"),
+        };
+        assert!(delay <= Duration::from_secs(1));
+        let due = delayed_ping_out.unwrap();
+        assert!(due >= before + delay);
+        for _ in 0..10 {
+            match fd.tick(Some(1), &mut delayed_ping_out, 3) {
+                PingPending => (),
+                _ => panic!("expected the tick to be skipped"),
+            }
+        }
+        assert_eq!(delayed_ping_out, Some(due));
+
+        // Once it has gone out, the next tick puts off another.
+        delayed_ping_out = None;
+        match fd.tick(Some(1), &mut delayed_ping_out, 3) {
+            PingDelayed(_) => (),
+            _ => panic!("expected the ping-out to be put off"),
+        }
+        assert!(delayed_ping_out.is_some());
+    }
+
+    #[test]
+    fn test_undecodable_datagrams_are_dropped() {
+        let mut config = Config::default();
+        config.set_addr("127.0.0.1:0".parse().unwrap());
+        config.set_ping_interval(Duration::from_millis(10));
+        let handle = PhiFD::start(vec![], Some(config)).unwrap();
+
+        let stranger = net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        stranger.send_to(b"not a gossip", handle.local_addr()).unwrap();
+        stranger.send_to(&[], handle.local_addr()).unwrap();
+        for _ in 0..100 {
+            if handle.detector().decode_failures() == 2 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(handle.detector().decode_failures(), 2);
+
+        // Still alive and well.
+        let peer = make_gossip(1, 1, vec![].into_iter(), GossipType::Syn);
+        stranger.send_to(&peer.write_to_bytes().unwrap(), handle.local_addr()).unwrap();
+        stranger.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+        let (len, _) = stranger.recv_from(&mut buf).unwrap();
+        let ack = parse_from_bytes::<Gossip>(&buf[..len]).unwrap();
+        assert_eq!(GossipType::from_u32(ack.get_kind()), Some(GossipType::Ack));
+        handle.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_start_reports_bind_failures() {
+        let taken = net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut config = Config::default();
+        config.set_addr(taken.local_addr().unwrap());
+        assert!(PhiFD::start(vec![], Some(config)).is_err());
+    }
+}
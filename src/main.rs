@@ -4,18 +4,16 @@ extern crate log;
 extern crate simple_logger;
 extern crate getopts;
 
-extern crate futures;
-extern crate tokio_core;
-extern crate tokio_io;
-
 use std::process;
 use std::env;
+use std::future;
 use std::time::Duration;
-use phifd::{PhiFD, Config};
+use phifd::{PhiFD, Config, EstimatorKind};
 use phifd::util;
 use getopts::Options;
 use log::LogLevel;
 use phifd::proto::msg::Member;
+use tokio::signal;
 
 fn main() {
     process::exit(match run() {
@@ -30,6 +28,38 @@ fn print_usage(program: &str, opts: Options) {
     print!("{}", opts.usage(&brief));
 }
 
+/// Resolves once we are asked to stop, by Ctrl-C or, on Unix, by SIGTERM as
+/// sent by `docker stop` for instance.
+async fn stop_requested() {
+    let interrupt = async {
+        if let Err(e) = signal::ctrl_c().await {
+            warn!("cannot listen for Ctrl-C: {}", e);
+            future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                warn!("cannot listen for SIGTERM: {}", e);
+                future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => (),
+        _ = terminate => (),
+    }
+    info!("asked to stop, leaving the cluster");
+}
+
 fn run() -> Result<(), ()> {
     simple_logger::init_with_level(LogLevel::Info).unwrap();
     let args: Vec<String> = env::args().collect();
@@ -45,7 +75,13 @@ fn run() -> Result<(), ()> {
         .optopt(
             "a",
             "addr",
-            "address to listen on, by default 0.0.0.0:12345",
+            "address to listen on, by default 0.0.0.0:12345 ([::]:12345 for IPv6)",
+            "ADDR",
+        )
+        .optopt(
+            "A",
+            "advertise_addr",
+            "address peers can reach us at, if not the one we listen on",
             "ADDR",
         )
         .optopt(
@@ -59,6 +95,23 @@ fn run() -> Result<(), ()> {
             "ticker_delay_secs",
             "Upper limit of a random delay to apply to periodic ping-outs",
             "DELAY"
+        )
+        .optopt(
+            "n",
+            "node_id",
+            "ID to identify this node by, the advertised address by default",
+            "NODE_ID"
+        )
+        .optflag(
+            "r",
+            "random_node_id",
+            "go by a random ID, new with every start, if no node_id is given"
+        )
+        .optopt(
+            "e",
+            "estimator",
+            "model of inter-arrival times, one of normal (default), exponential, lognormal or ewma",
+            "ESTIMATOR"
         );
 
 
@@ -84,7 +137,7 @@ fn run() -> Result<(), ()> {
 
     let introducer_ips = introducers
         .iter()
-        .map(|addr| util::resolve_first_ipv4(addr))
+        .map(|addr| util::resolve_first(addr))
         .filter_map(|addr_result| match addr_result {
             Ok(some_or_none) => some_or_none,
             Err(_) => None,
@@ -113,26 +166,53 @@ fn run() -> Result<(), ()> {
                cfg.set_ticker_delay(secs);
            });
 
+    if let Some(s) = matches.opt_str("estimator") {
+        match EstimatorKind::from_name(&s) {
+            Some(estimator) => {
+                cfg.set_estimator(estimator);
+            }
+            None => {
+                error!("unknown estimator: {}", s);
+                print_usage(&prog, opts);
+                return Err(());
+            }
+        }
+    }
+
+    matches.opt_str("node_id")
+           .map(|s| {
+               cfg.set_node_id(s);
+           });
+    cfg.set_random_node_id(matches.opt_present("random_node_id"));
+
     let addrstr = matches.opt_str("addr").unwrap_or(
         "0.0.0.0:12345".to_string(),
     );
 
-    let sockaddr = util::resolve_first_ipv4(&addrstr)
+    let sockaddr = util::resolve_first(&addrstr)
         .expect("Error resolving listen address")
         .expect("No resolution for given listen address");
 
     cfg.set_addr(sockaddr);
 
+    if let Some(advertise) = matches.opt_str("advertise_addr") {
+        let advertise_addr = util::resolve_first(&advertise)
+            .expect("Error resolving advertise address")
+            .expect("No resolution for given advertise address");
+        cfg.set_advertise_addr(advertise_addr);
+    }
+
     info!("starting failure detector now on {}", &sockaddr);
-    let mut fd = if introducers.len() != 0 {
+    let fd = if introducers.len() != 0 {
         let members = introducer_ips
             .into_iter()
-            .map(|intro| util::member_from_sockaddr(intro).unwrap())
+            .map(util::member_from_sockaddr)
             .collect::<Vec<Member>>();
         PhiFD::with_members(members, Some(cfg))
     } else {
         PhiFD::new(Some(cfg))
     };
-    fd.run();
-    Ok(())
+    fd.run_until(stop_requested()).map_err(|e| {
+        error!("failure detector stopped: {}", e);
+    })
 }
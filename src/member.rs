@@ -1,88 +1,70 @@
-use std::collections::VecDeque;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
 use std::time::{Instant, Duration};
 
-use proto::msg::Member;
-use statrs::distribution::{Normal, Univariate};
-use statrs::statistics::{Mean, Variance};
-use statrs::statistics::Statistics;
-use std::f64::consts::PI;
-
-/// This type is used to identify a member uniquely using its IPv4 number and
-/// port.
-pub type MemberID = (u32, u16);
-
-
-/// A normal distribution maintained using weighted averages.
-#[derive(Clone, Debug)]
-pub struct InterArrivalDistribution {
-    mu: f64,
-    sigma: f64,
-    var: f64,
-    alpha: f64,
-
-    std_normal_variate: Normal,
+use crate::proto::msg::Member;
+use crate::config::Config;
+use crate::estimator::{InterArrivalEstimator, EstimatorKind};
+use crate::util::{duration_secs, member_addr};
+
+/// This type is used to identify a member uniquely. Members are known by
+/// their node ID, except for those we only know the address of (see
+/// `util::member_addr`), like seeds we haven't heard from yet and nodes that
+/// predate node IDs.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MemberID {
+    Node(String),
+    Addr(SocketAddr),
 }
 
-impl InterArrivalDistribution {
-    pub fn new(mu: f64, sigma: f64, alpha: f64) -> InterArrivalDistribution {
-        InterArrivalDistribution {
-            mu: mu,
-            sigma: sigma,
-            var: sigma * sigma,
-            alpha: alpha,
-            std_normal_variate: Normal::new(0f64, 1f64).unwrap(),
+impl MemberID {
+    /// The ID `member` goes by.
+    pub fn of(member: &Member) -> MemberID {
+        if member.has_node_id() {
+            MemberID::Node(member.get_node_id().to_string())
+        } else {
+            MemberID::Addr(member_addr(member))
         }
     }
 
-    pub fn mean(&self) -> f64 {
-        self.mu
-    }
-
-    pub fn stddev(&self) -> f64 {
-        self.sigma
-    }
-
-    pub fn variance(&self) -> f64 {
-        self.var
-    }
-
-    pub fn update(&mut self, val: f64) {
-
-        let new_mean = self.alpha * self.mean() + (1f64 - self.alpha) * val;
-
-        let new_var = self.alpha * self.variance() +
-            (1f64 - self.alpha) * (val - self.mean()) * (val - new_mean);
-
-        println!("Update with {:0.4}, mean: {:0.4} -> {:0.4}, var: {:0.4}  -> {:0.4}",
-                    val, self.mean(), new_mean, self.variance(), new_var);
-        self.mu = new_mean;
-        self.var = new_var;
-        self.sigma = new_var.sqrt();
+    pub fn is_node(&self) -> bool {
+        match *self {
+            MemberID::Node(_) => true,
+            MemberID::Addr(_) => false,
+        }
     }
+}
 
-    pub fn cdf(&self, val: f64) -> Option<f64> {
-        if self.sigma == 0f64 {
-            None
-        } else {
-            let z = (val - self.mean()) / self.stddev();
-            let cdf = self.std_normal_variate.cdf(z);
-            Some(cdf)
+impl fmt::Display for MemberID {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MemberID::Node(ref id) => write!(f, "{}", id),
+            MemberID::Addr(ref addr) => write!(f, "{}", addr),
         }
     }
 }
 
+
+/// Keeps track of when we last heard from a member, and feeds the observed
+/// inter-arrival times to an estimator of their distribution.
 #[derive(Clone, Debug)]
 pub struct InterArrivalWindow {
-    distribution: Option<InterArrivalDistribution>,
+    estimator: Box<dyn InterArrivalEstimator>,
     last_arrival_at: Option<Instant>,
     size: usize,
 }
 
 impl InterArrivalWindow {
     pub fn of_size(size: usize) -> InterArrivalWindow {
+        InterArrivalWindow::with_estimator(EstimatorKind::Normal, size)
+    }
+
+    pub fn with_estimator(kind: EstimatorKind, size: usize) -> InterArrivalWindow {
         assert!(size >= 3);
         InterArrivalWindow {
-            distribution: None,
+            estimator: kind.build(size),
             size: size,
             last_arrival_at: None,
         }
@@ -96,24 +78,18 @@ impl InterArrivalWindow {
     /// adding an observation `value` to the window.
     pub fn update(&mut self, value: Duration) {
 
-        let duration_secs = value.as_secs() as f64
-                          + value.subsec_nanos() as f64 * 1e-9;
+        let secs = duration_secs(value);
 
-        if let Some(ref mut interdist) = self.distribution {
-            interdist.update(duration_secs);
-        } else {
-            self.distribution = Some(
-                InterArrivalDistribution::new(duration_secs, 0f64, 0.9f64)
-            );
-        }
-        //let (m, v) = self.running_stats.unwrap();
-        let (m, v) = self.distribution
-                .as_ref()
-                .map(|d| (d.mean(), d.variance()))
-                .expect("distribution should have been constructed by now");
+        self.estimator.update(secs);
 
-            println!("after update, mean: {}, var: {}", m, v);
+        debug!("after update with {:0.4}, mean: {:?}, var: {:?}",
+               secs, self.mean(), self.variance());
+    }
 
+    /// Prime the estimator with a prior belief about the mean and standard
+    /// deviation of inter-arrival times, until real samples take over.
+    pub fn seed(&mut self, mean: Duration, stddev: Duration) {
+        self.estimator.seed(duration_secs(mean), duration_secs(stddev));
     }
 
     /// Trigger an update of the mean and variance estimates of the
@@ -126,31 +102,59 @@ impl InterArrivalWindow {
         self.last_arrival_at = Some(arrival_time);
     }
 
-    pub fn phi(&self, at: Instant) -> Option<f64> {
+    pub fn mean(&self) -> Option<f64> {
+        self.estimator.mean()
+    }
+
+    pub fn variance(&self) -> Option<f64> {
+        self.estimator.variance()
+    }
+
+    pub fn stddev(&self) -> Option<f64> {
+        self.variance().map(f64::sqrt)
+    }
+
+    /// The suspicion level at `at`. A silence of up to `acceptable_pause` on
+    /// top of the usual inter-arrival time is not held against the member,
+    /// which is the same as shifting the mean of the distribution by that
+    /// much. The standard deviation is taken to be at least
+    /// `min_std_deviation`.
+    pub fn phi(&self, at: Instant, acceptable_pause: Duration, min_std_deviation: Duration)
+        -> Option<f64> {
         if let Some(last_arrival) = self.last_arrival_at {
             if last_arrival > at {
                 None
             } else {
-                self.distribution.as_ref().and_then(|dist| {
-                    let dur = at.duration_since(last_arrival);
-                    let dur_secs = dur.as_secs() as f64 + dur.subsec_nanos() as f64 * 1e-9;
-                    dist.cdf(dur_secs).map(|cdf| -(1.0 - cdf).log10())
-                })
+                let elapsed = duration_secs(at.duration_since(last_arrival))
+                            - duration_secs(acceptable_pause);
+                self.estimator.phi(elapsed, duration_secs(min_std_deviation))
             }
         } else {
             None
         }
     }
+}
 
-    #[cfg(test)]
-    pub fn mean(&self) -> Option<f64> {
-        self.distribution.as_ref().map(|d| Mean::mean(d))
-    }
+/// Where a member is in its lifecycle, as far as this process can tell.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemberStatus {
+    /// We are hearing from the member about as often as we expect to.
+    Alive,
+    /// The member's phi has crossed the suspicion threshold. If it stays
+    /// there for long enough, the member is declared dead.
+    Suspect,
+    /// The member has been suspected for longer than the suspect timeout.
+    Dead,
+    /// The member told us it was leaving the cluster.
+    Left,
+}
 
-    #[cfg(test)]
-    pub fn variance(&self) -> Option<f64> {
-        self.distribution.as_ref().map(|d| Variance::variance(d))
-    }
+/// The phi another member told us it has for a member, see
+/// `MemberState::add_opinion`.
+#[derive(Clone, Debug)]
+struct Opinion {
+    phi: f64,
+    at: Instant,
 }
 
 /// This stores this process' knowledge about a given member at any given time.
@@ -162,55 +166,376 @@ pub struct MemberState {
     /// The last time when this member's state was updated.
     timestamp: Instant,
 
-    window_size: usize,
+    acceptable_heartbeat_pause: Duration,
+
+    min_std_deviation: Duration,
 
     /// Book keeping for estimating the distribution of inter-arrival times of
     /// pings from this node.
-    inter_arrival_window: Option<InterArrivalWindow>,
+    inter_arrival_window: InterArrivalWindow,
+
+    status: MemberStatus,
+
+    /// When the member entered its current status.
+    status_since: Instant,
+
+    /// When news of the member last amounted to more than a heartbeat: it
+    /// joined, changed status, restarted or moved.
+    changed_at: Instant,
+
+    /// The protocol version and capabilities the member last told us it
+    /// has. Only the member itself can tell us, so these stay at 0 until we
+    /// hear from it directly.
+    version: u32,
+    capabilities: u64,
+
+    /// The latest phi every other member gossiped for this one, by who
+    /// gossiped it.
+    opinions: HashMap<MemberID, Opinion>,
+
+    /// The phi at which a member counts as suspecting this one, see
+    /// `ClusterSuspicion::suspecting`.
+    suspicion_threshold: f64,
 }
 
 impl MemberState {
-    pub fn from_member(member: Member, window_size: usize) -> MemberState {
+    /// Start tracking a member we have just heard of. Hearing of it counts as
+    /// its first arrival, and until we have real samples, its inter-arrival
+    /// times are assumed to follow the bootstrap prior from `config`.
+    pub fn from_member(member: Member, config: &Config) -> MemberState {
+        let now = Instant::now();
+        let (prior_mean, prior_std) = config.bootstrap_prior();
+        let mut window = InterArrivalWindow::with_estimator(config.estimator, config.window_size);
+        window.seed(prior_mean, prior_std);
+        window.tick(now);
+        let status = if member.get_left() {
+            MemberStatus::Left
+        } else {
+            MemberStatus::Alive
+        };
         MemberState {
             member: member,
-            timestamp: Instant::now(),
-            window_size: window_size,
-            inter_arrival_window: None,
+            timestamp: now,
+            acceptable_heartbeat_pause: config.acceptable_heartbeat_pause,
+            min_std_deviation: config.min_std_deviation,
+            inter_arrival_window: window,
+            status: status,
+            status_since: now,
+            changed_at: now,
+            version: 0,
+            capabilities: 0,
+            opinions: HashMap::new(),
+            suspicion_threshold: config.suspicion_threshold,
         }
     }
 
-    pub fn merge(&mut self, _suspicion: f64, heartbeat: u64) {
-        if self.member.get_heartbeat() < heartbeat {
-            self.member.set_heartbeat(heartbeat);
+    /// Take in what we heard about this member. Heartbeats are ordered by
+    /// `(incarnation, heartbeat)`, since a member's heartbeat counter starts
+    /// over when it restarts with a new incarnation. A newer heartbeat than
+    /// the one we know of means the member is alive, so this also clears any
+    /// suspicion, and comes with the member's current address. A member that
+    /// left only comes back with a new incarnation, and news of a member
+    /// leaving that is at least as recent as what we know of it is taken at
+    /// its word.
+    pub fn merge(&mut self, incoming: &Member) {
+        let now = Instant::now();
+        let known = (self.member.get_incarnation(), self.member.get_heartbeat());
+        let news = (incoming.get_incarnation(), incoming.get_heartbeat());
+        if known < news {
+            let restarted = known.0 < news.0;
+            let moved = member_addr(incoming) != member_addr(&self.member);
+            self.member.set_incarnation(news.0);
+            self.member.set_heartbeat(news.1);
+            self.member.set_ip(incoming.get_ip());
+            self.member.set_port(incoming.get_port());
+            if incoming.has_ipv6() {
+                self.member.set_ipv6(incoming.get_ipv6().to_vec());
+            } else {
+                self.member.clear_ipv6();
+            }
+            if restarted {
+                self.member.set_left(false);
+            }
+            if restarted || moved {
+                self.changed_at = now;
+            }
+
+            self.timestamp = now;
+
+            self.inter_arrival_window.tick(now);
 
-            self.timestamp = Instant::now();
+            match self.status {
+                MemberStatus::Suspect | MemberStatus::Dead => {
+                    self.set_status(MemberStatus::Alive, now);
+                }
+                MemberStatus::Left if restarted => {
+                    self.set_status(MemberStatus::Alive, now);
+                }
+                MemberStatus::Alive | MemberStatus::Left => {}
+            }
+        }
 
-            let wnd_sz = self.window_size;
-            self.inter_arrival_window
-                .get_or_insert_with(|| InterArrivalWindow::of_size(wnd_sz))
-                .tick(Instant::now());
+        if incoming.get_left() && known <= news && self.status != MemberStatus::Left {
+            self.member.set_left(true);
+            self.set_status(MemberStatus::Left, now);
         }
     }
 
+    /// Move the member along its lifecycle based on its phi at `at`, see
+    /// `damped_phi` for `extra_pause`. An alive member is suspected once its
+    /// phi reaches `threshold`, and a suspect is declared dead once it has
+    /// been suspected for `suspect_timeout`. If a suspect's phi drops back
+    /// below the threshold, it is alive again. Returns the new status if it
+    /// changed.
+    pub fn update_status(
+        &mut self,
+        at: Instant,
+        threshold: f64,
+        suspect_timeout: Duration,
+        extra_pause: Duration,
+    ) -> Option<MemberStatus> {
+        let suspicious = self.damped_phi(at, extra_pause)
+            .map(|phi| phi >= threshold)
+            .unwrap_or(false);
+        let next = match self.status {
+            MemberStatus::Alive if suspicious => MemberStatus::Suspect,
+            MemberStatus::Suspect if !suspicious => MemberStatus::Alive,
+            MemberStatus::Suspect if at >= self.status_since + suspect_timeout => {
+                MemberStatus::Dead
+            }
+            status => status,
+        };
+        if next != self.status {
+            self.set_status(next, at);
+            Some(next)
+        } else {
+            None
+        }
+    }
+
+    fn set_status(&mut self, status: MemberStatus, at: Instant) {
+        self.status = status;
+        self.status_since = at;
+        self.changed_at = at;
+    }
+
+    /// Whether the member has been dead or gone for long enough that we can
+    /// forget about it.
+    pub fn is_reapable(&self, at: Instant, reap_timeout: Duration) -> bool {
+        match self.status {
+            MemberStatus::Dead | MemberStatus::Left => at >= self.status_since + reap_timeout,
+            MemberStatus::Alive | MemberStatus::Suspect => false,
+        }
+    }
+
+    /// Take note of the protocol version and capabilities the member
+    /// advertised in gossip it sent us.
+    pub fn set_protocol(&mut self, version: u32, capabilities: u64) {
+        self.version = version;
+        self.capabilities = capabilities;
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Whether the member told us it supports all of `capabilities`.
+    pub fn supports(&self, capabilities: u64) -> bool {
+        self.capabilities & capabilities == capabilities
+    }
+
+    /// Take note that `reporter` had a phi of `phi` for this member as of
+    /// `at`, replacing whatever it told us before.
+    pub fn add_opinion(&mut self, reporter: MemberID, phi: f64, at: Instant) {
+        if phi.is_nan() {
+            return;
+        }
+        self.opinions.insert(reporter, Opinion { phi: phi, at: at });
+    }
+
+    /// Forget the opinions that are older than `max_age` at `at`.
+    pub fn expire_opinions(&mut self, at: Instant, max_age: Duration) {
+        self.opinions.retain(|_, opinion| at < opinion.at + max_age);
+    }
+
+    /// What the other members make of this one, or `None` if none of them
+    /// told us.
+    pub fn cluster_suspicion(&self) -> Option<ClusterSuspicion> {
+        if self.opinions.is_empty() {
+            return None;
+        }
+        let mut phis = self.opinions.values().map(|o| o.phi).collect::<Vec<_>>();
+        phis.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        let n = phis.len();
+        let median = if n % 2 == 1 {
+            phis[n / 2]
+        } else {
+            (phis[n / 2 - 1] + phis[n / 2]) / 2f64
+        };
+        Some(ClusterSuspicion {
+            reporters: n,
+            suspecting: phis.iter().filter(|&&phi| phi >= self.suspicion_threshold).count(),
+            max_phi: phis[n - 1],
+            median_phi: median,
+        })
+    }
+
+    pub fn status(&self) -> MemberStatus {
+        self.status
+    }
+
+    pub fn status_since(&self) -> Instant {
+        self.status_since
+    }
+
+    /// The last time we heard a newer heartbeat from the member.
+    pub fn last_seen(&self) -> Instant {
+        self.timestamp
+    }
+
+    /// The last time the member joined, changed status, restarted or moved.
+    pub fn changed_at(&self) -> Instant {
+        self.changed_at
+    }
+
     pub fn phi(&self, at: Instant) -> Option<f64> {
-        self.inter_arrival_window.as_ref().and_then(
-            |iaw| iaw.phi(at),
+        self.damped_phi(at, Duration::from_secs(0))
+    }
+
+    /// Our phi for the member at `at` if we let it be silent for
+    /// `extra_pause` longer than its acceptable heartbeat pause. This is how
+    /// an unhealthy node holds back its suspicions, see `LocalHealth`.
+    pub fn damped_phi(&self, at: Instant, extra_pause: Duration) -> Option<f64> {
+        self.inter_arrival_window.phi(
+            at,
+            self.acceptable_heartbeat_pause + extra_pause,
+            self.min_std_deviation,
         )
     }
 
     pub fn get_id(&self) -> MemberID {
-        (self.member.get_ip(), self.member.get_port() as u16)
+        MemberID::of(&self.member)
+    }
+
+    pub fn get_addr(&self) -> SocketAddr {
+        member_addr(&self.member)
     }
 
     pub fn get_member_ref<'a>(&'a self) -> &'a Member {
         &self.member
     }
+
+    /// The member as we gossip it at `at`, with our phi for it as its
+    /// suspicion, damped by `extra_pause` (see `damped_phi`).
+    pub fn gossip_record(&self, at: Instant, extra_pause: Duration) -> Member {
+        let mut member = self.member.clone();
+        member.set_suspicion(self.damped_phi(at, extra_pause).unwrap_or(0f64));
+        member
+    }
+
+    /// What we know about the member as of `at`, see `MemberSnapshot`.
+    pub fn snapshot(&self, at: Instant) -> MemberSnapshot {
+        MemberSnapshot {
+            id: self.get_id(),
+            addr: member_addr(&self.member),
+            incarnation: self.member.get_incarnation(),
+            heartbeat: self.member.get_heartbeat(),
+            last_seen: self.timestamp,
+            status: self.status,
+            version: self.version,
+            capabilities: self.capabilities,
+            phi: self.phi(at),
+            cluster: self.cluster_suspicion(),
+            mean: self.inter_arrival_window.mean(),
+            stddev: self.inter_arrival_window.stddev(),
+        }
+    }
+}
+
+/// A copy of what we know about a member at some point in time, for handing
+/// out to code outside the detector.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemberSnapshot {
+    pub id: MemberID,
+    pub addr: SocketAddr,
+    pub incarnation: u64,
+    pub heartbeat: u64,
+    /// The last time we heard a newer heartbeat from the member.
+    pub last_seen: Instant,
+    pub status: MemberStatus,
+    /// The protocol version the member speaks, 0 if unknown.
+    pub version: u32,
+    /// The member's capability bitmap, see `util::CAPABILITIES`.
+    pub capabilities: u64,
+    /// Our own phi for the member, as is, without the damping an unhealthy
+    /// node applies before acting on it (see `MemberState::damped_phi`).
+    pub phi: Option<f64>,
+    /// What other members make of it.
+    pub cluster: Option<ClusterSuspicion>,
+    /// Mean of the member's inter-arrival times, in seconds.
+    pub mean: Option<f64>,
+    /// Standard deviation of the member's inter-arrival times, in seconds.
+    pub stddev: Option<f64>,
+}
+
+
+/// What the other members that gossip with us make of a member, going by the
+/// phi they gossiped for it (see `Member.suspicion`) within the last
+/// `Config::opinion_max_age`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClusterSuspicion {
+    /// How many members told us their phi for the member.
+    pub reporters: usize,
+    /// How many of those have it at or above our suspicion threshold.
+    pub suspecting: usize,
+    pub max_phi: f64,
+    pub median_phi: f64,
+}
+
+
+/// What we remember about a member after reaping it, so that stale gossip
+/// from peers that haven't caught up yet doesn't bring it back.
+#[derive(Clone, Debug)]
+pub struct Tombstone {
+    /// The member's incarnation and heartbeat when we reaped it.
+    incarnation: u64,
+    heartbeat: u64,
+    expires_at: Instant,
+}
+
+impl Tombstone {
+    pub fn new(incarnation: u64, heartbeat: u64, expires_at: Instant) -> Tombstone {
+        Tombstone {
+            incarnation: incarnation,
+            heartbeat: heartbeat,
+            expires_at: expires_at,
+        }
+    }
+
+    /// Whether hearing `(incarnation, heartbeat)` for the reaped member means
+    /// it is really back, as opposed to being old news.
+    pub fn is_superseded_by(&self, incarnation: u64, heartbeat: u64) -> bool {
+        (incarnation, heartbeat) > (self.incarnation, self.heartbeat)
+    }
+
+    pub fn is_expired(&self, at: Instant) -> bool {
+        at >= self.expires_at
+    }
 }
 
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::util::member_from_address;
+
+    fn news(incarnation: u64, heartbeat: u64, left: bool) -> Member {
+        let mut member = member_from_address("127.0.0.1:12345").unwrap();
+        member.set_incarnation(incarnation);
+        member.set_heartbeat(heartbeat);
+        member.set_left(left);
+        member
+    }
 
     #[test]
     fn it_works() {
@@ -244,4 +569,188 @@ mod tests {
         assert!((interval.variance().unwrap() - 3104.33333f64).abs() <= 10e-6);
         assert_eq!(interval.size(), 3);
     }
+
+    #[test]
+    fn test_inter_arrival_interval_window_evicts_oldest() {
+        let mut interval = InterArrivalWindow::of_size(3);
+
+        interval.update(Duration::from_secs(100));
+        interval.update(Duration::from_secs(3));
+        interval.update(Duration::from_secs(4));
+        interval.update(Duration::from_secs(5));
+
+        // The 100s sample should have fallen off the window by now.
+        assert!((interval.mean().unwrap() - 4f64).abs() <= 1e-6);
+        assert!((interval.variance().unwrap() - 1f64).abs() <= 1e-6);
+    }
+
+    #[test]
+    fn test_inter_arrival_window_phi_with_pause_and_min_stddev() {
+        let start = Instant::now();
+        let mut interval = InterArrivalWindow::of_size(3);
+        for i in 0..4 {
+            interval.tick(start + Duration::from_secs(i));
+        }
+        let at = start + Duration::from_secs(5);
+        let no_pause = Duration::from_secs(0);
+        let min_std = Duration::from_millis(100);
+
+        // Perfectly regular pings have no spread without a floor on it.
+        assert!(interval.phi(at, no_pause, no_pause).is_none());
+
+        let phi = interval.phi(at, no_pause, min_std).unwrap();
+        let paused_phi = interval.phi(at, Duration::from_secs(1), min_std).unwrap();
+        assert!(phi.is_finite());
+        assert!(paused_phi < phi);
+    }
+
+    #[test]
+    fn test_new_member_has_phi_from_the_start() {
+        let mut config = Config::default();
+        config.set_ping_interval(Duration::from_secs(2));
+        let member = member_from_address("127.0.0.1:12345").unwrap();
+        let state = MemberState::from_member(member, &config);
+
+        let now = Instant::now();
+        let early = state.phi(now).unwrap();
+        let late = state.phi(now + Duration::from_secs(6)).unwrap();
+        assert!(early < 1f64);
+        assert!(late > early);
+
+        // The prior's mean and spread are exactly what we configured.
+        let window = &state.inter_arrival_window;
+        assert!((window.mean().unwrap() - 2f64).abs() <= 1e-9);
+        assert!((window.stddev().unwrap() - 0.5f64).abs() <= 1e-9);
+    }
+
+    #[test]
+    fn test_member_status_lifecycle() {
+        let config = Config::default();
+        let member = member_from_address("127.0.0.1:12345").unwrap();
+        let mut state = MemberState::from_member(member, &config);
+        let threshold = 8f64;
+        let timeout = Duration::from_secs(5);
+        let no_pause = Duration::from_secs(0);
+        let start = state.status_since();
+        assert_eq!(state.status(), MemberStatus::Alive);
+
+        assert_eq!(state.update_status(start, threshold, timeout, no_pause), None);
+
+        let suspected_at = start + Duration::from_secs(10);
+        assert_eq!(
+            state.update_status(suspected_at, threshold, timeout, no_pause),
+            Some(MemberStatus::Suspect)
+        );
+        assert_eq!(
+            state.update_status(suspected_at + Duration::from_secs(1), threshold, timeout,
+                                no_pause),
+            None
+        );
+        assert_eq!(
+            state.update_status(suspected_at + timeout, threshold, timeout, no_pause),
+            Some(MemberStatus::Dead)
+        );
+
+        // A fresh heartbeat brings it back.
+        state.merge(&news(0, 1, false));
+        assert_eq!(state.status(), MemberStatus::Alive);
+    }
+
+    #[test]
+    fn test_merge_orders_by_incarnation_then_heartbeat() {
+        let config = Config::default();
+        let member = member_from_address("127.0.0.1:12345").unwrap();
+        let mut state = MemberState::from_member(member, &config);
+
+        state.merge(&news(1, 100, false));
+        assert_eq!(state.get_member_ref().get_heartbeat(), 100);
+
+        // A stale heartbeat from the same incarnation is ignored.
+        state.merge(&news(1, 50, false));
+        assert_eq!(state.get_member_ref().get_heartbeat(), 100);
+
+        // After a restart, the heartbeat counter starts over.
+        state.merge(&news(2, 0, false));
+        assert_eq!(state.get_member_ref().get_incarnation(), 2);
+        assert_eq!(state.get_member_ref().get_heartbeat(), 0);
+
+        // And heartbeats from the previous incarnation are old news.
+        state.merge(&news(1, 101, false));
+        assert_eq!(state.get_member_ref().get_incarnation(), 2);
+    }
+
+    #[test]
+    fn test_left_members_only_come_back_with_a_new_incarnation() {
+        let config = Config::default();
+        let mut state = MemberState::from_member(news(1, 10, false), &config);
+
+        // Stale news of a departure doesn't count.
+        state.merge(&news(1, 9, true));
+        assert_eq!(state.status(), MemberStatus::Alive);
+
+        state.merge(&news(1, 10, true));
+        assert_eq!(state.status(), MemberStatus::Left);
+        assert!(state.get_member_ref().get_left());
+
+        // Gossip from the same incarnation that missed the departure.
+        state.merge(&news(1, 11, false));
+        assert_eq!(state.status(), MemberStatus::Left);
+
+        state.merge(&news(2, 0, false));
+        assert_eq!(state.status(), MemberStatus::Alive);
+        assert!(!state.get_member_ref().get_left());
+    }
+
+    #[test]
+    fn test_address_follows_the_latest_news() {
+        let config = Config::default();
+        let mut member = news(1, 10, false);
+        member.set_node_id("node-a".to_string());
+        let mut state = MemberState::from_member(member, &config);
+        assert_eq!(state.get_id(), MemberID::Node("node-a".to_string()));
+
+        let mut moved = member_from_address("[2001:db8::1]:12345").unwrap();
+        moved.set_node_id("node-a".to_string());
+        moved.set_incarnation(2);
+        state.merge(&moved);
+        assert_eq!(state.get_addr(), "[2001:db8::1]:12345".parse().unwrap());
+
+        // Old news from the old address doesn't move it back.
+        let mut stale = news(1, 11, false);
+        stale.set_node_id("node-a".to_string());
+        state.merge(&stale);
+        assert_eq!(state.get_addr(), "[2001:db8::1]:12345".parse().unwrap());
+        assert_eq!(state.get_id(), MemberID::Node("node-a".to_string()));
+    }
+
+    #[test]
+    fn test_cluster_suspicion() {
+        let mut config = Config::default();
+        config.set_suspicion_threshold(8f64);
+        let mut state = MemberState::from_member(news(1, 10, false), &config);
+        assert_eq!(state.cluster_suspicion(), None);
+
+        let start = Instant::now();
+        let reporter = |n: u16| MemberID::Addr(SocketAddr::from(([127, 0, 0, 1], n)));
+        state.add_opinion(reporter(1), 1f64, start);
+        state.add_opinion(reporter(2), 9f64, start);
+        state.add_opinion(reporter(3), 3f64, start + Duration::from_secs(5));
+        state.add_opinion(reporter(4), f64::NAN, start);
+        assert_eq!(state.cluster_suspicion(), Some(ClusterSuspicion {
+            reporters: 3,
+            suspecting: 1,
+            max_phi: 9f64,
+            median_phi: 3f64,
+        }));
+
+        // A reporter changing its mind replaces its opinion.
+        state.add_opinion(reporter(2), 2f64, start + Duration::from_secs(5));
+        let cluster = state.cluster_suspicion().unwrap();
+        assert_eq!((cluster.suspecting, cluster.max_phi), (0, 3f64));
+
+        // Opinions don't last forever.
+        state.expire_opinions(start + Duration::from_secs(10), Duration::from_secs(10));
+        let cluster = state.cluster_suspicion().unwrap();
+        assert_eq!((cluster.reporters, cluster.median_phi), (2, 2.5f64));
+    }
 }
@@ -28,6 +28,10 @@ pub struct Member {
     port: ::std::option::Option<u32>,
     suspicion: ::std::option::Option<f64>,
     heartbeat: ::std::option::Option<u64>,
+    incarnation: ::std::option::Option<u64>,
+    left: ::std::option::Option<bool>,
+    ipv6: ::protobuf::SingularField<::std::vec::Vec<u8>>,
+    node_id: ::protobuf::SingularField<::std::string::String>,
     // special fields
     unknown_fields: ::protobuf::UnknownFields,
     cached_size: ::protobuf::CachedSize,
@@ -158,6 +162,148 @@ impl Member {
     fn mut_heartbeat_for_reflect(&mut self) -> &mut ::std::option::Option<u64> {
         &mut self.heartbeat
     }
+
+    // optional uint64 incarnation = 5;
+
+    pub fn clear_incarnation(&mut self) {
+        self.incarnation = ::std::option::Option::None;
+    }
+
+    pub fn has_incarnation(&self) -> bool {
+        self.incarnation.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_incarnation(&mut self, v: u64) {
+        self.incarnation = ::std::option::Option::Some(v);
+    }
+
+    pub fn get_incarnation(&self) -> u64 {
+        self.incarnation.unwrap_or(0)
+    }
+
+    fn get_incarnation_for_reflect(&self) -> &::std::option::Option<u64> {
+        &self.incarnation
+    }
+
+    fn mut_incarnation_for_reflect(&mut self) -> &mut ::std::option::Option<u64> {
+        &mut self.incarnation
+    }
+
+    // optional bool left = 6;
+
+    pub fn clear_left(&mut self) {
+        self.left = ::std::option::Option::None;
+    }
+
+    pub fn has_left(&self) -> bool {
+        self.left.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_left(&mut self, v: bool) {
+        self.left = ::std::option::Option::Some(v);
+    }
+
+    pub fn get_left(&self) -> bool {
+        self.left.unwrap_or(false)
+    }
+
+    fn get_left_for_reflect(&self) -> &::std::option::Option<bool> {
+        &self.left
+    }
+
+    fn mut_left_for_reflect(&mut self) -> &mut ::std::option::Option<bool> {
+        &mut self.left
+    }
+
+    // optional bytes ipv6 = 7;
+
+    pub fn clear_ipv6(&mut self) {
+        self.ipv6.clear();
+    }
+
+    pub fn has_ipv6(&self) -> bool {
+        self.ipv6.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_ipv6(&mut self, v: ::std::vec::Vec<u8>) {
+        self.ipv6 = ::protobuf::SingularField::some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_ipv6(&mut self) -> &mut ::std::vec::Vec<u8> {
+        if self.ipv6.is_none() {
+            self.ipv6.set_default();
+        }
+        self.ipv6.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_ipv6(&mut self) -> ::std::vec::Vec<u8> {
+        self.ipv6.take().unwrap_or_else(|| ::std::vec::Vec::new())
+    }
+
+    pub fn get_ipv6(&self) -> &[u8] {
+        match self.ipv6.as_ref() {
+            Some(v) => &v,
+            None => &[],
+        }
+    }
+
+    fn get_ipv6_for_reflect(&self) -> &::protobuf::SingularField<::std::vec::Vec<u8>> {
+        &self.ipv6
+    }
+
+    fn mut_ipv6_for_reflect(&mut self) -> &mut ::protobuf::SingularField<::std::vec::Vec<u8>> {
+        &mut self.ipv6
+    }
+
+    // optional string node_id = 8;
+
+    pub fn clear_node_id(&mut self) {
+        self.node_id.clear();
+    }
+
+    pub fn has_node_id(&self) -> bool {
+        self.node_id.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_node_id(&mut self, v: ::std::string::String) {
+        self.node_id = ::protobuf::SingularField::some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_node_id(&mut self) -> &mut ::std::string::String {
+        if self.node_id.is_none() {
+            self.node_id.set_default();
+        }
+        self.node_id.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_node_id(&mut self) -> ::std::string::String {
+        self.node_id.take().unwrap_or_else(|| ::std::string::String::new())
+    }
+
+    pub fn get_node_id(&self) -> &str {
+        match self.node_id.as_ref() {
+            Some(v) => &v,
+            None => "",
+        }
+    }
+
+    fn get_node_id_for_reflect(&self) -> &::protobuf::SingularField<::std::string::String> {
+        &self.node_id
+    }
+
+    fn mut_node_id_for_reflect(&mut self) -> &mut ::protobuf::SingularField<::std::string::String> {
+        &mut self.node_id
+    }
 }
 
 impl ::protobuf::Message for Member {
@@ -209,6 +355,26 @@ impl ::protobuf::Message for Member {
                     let tmp = is.read_uint64()?;
                     self.heartbeat = ::std::option::Option::Some(tmp);
                 },
+                5 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint64()?;
+                    self.incarnation = ::std::option::Option::Some(tmp);
+                },
+                6 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_bool()?;
+                    self.left = ::std::option::Option::Some(tmp);
+                },
+                7 => {
+                    ::protobuf::rt::read_singular_bytes_into(wire_type, is, &mut self.ipv6)?;
+                },
+                8 => {
+                    ::protobuf::rt::read_singular_string_into(wire_type, is, &mut self.node_id)?;
+                },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
                 },
@@ -233,6 +399,18 @@ impl ::protobuf::Message for Member {
         if let Some(v) = self.heartbeat {
             my_size += ::protobuf::rt::value_size(4, v, ::protobuf::wire_format::WireTypeVarint);
         }
+        if let Some(v) = self.incarnation {
+            my_size += ::protobuf::rt::value_size(5, v, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if let Some(v) = self.left {
+            my_size += 2;
+        }
+        if let Some(ref v) = self.ipv6.as_ref() {
+            my_size += ::protobuf::rt::bytes_size(7, &v);
+        }
+        if let Some(ref v) = self.node_id.as_ref() {
+            my_size += ::protobuf::rt::string_size(8, &v);
+        }
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
@@ -251,6 +429,18 @@ impl ::protobuf::Message for Member {
         if let Some(v) = self.heartbeat {
             os.write_uint64(4, v)?;
         }
+        if let Some(v) = self.incarnation {
+            os.write_uint64(5, v)?;
+        }
+        if let Some(v) = self.left {
+            os.write_bool(6, v)?;
+        }
+        if let Some(ref v) = self.ipv6.as_ref() {
+            os.write_bytes(7, &v)?;
+        }
+        if let Some(ref v) = self.node_id.as_ref() {
+            os.write_string(8, &v)?;
+        }
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -315,6 +505,26 @@ impl ::protobuf::MessageStatic for Member {
                     Member::get_heartbeat_for_reflect,
                     Member::mut_heartbeat_for_reflect,
                 ));
+                fields.push(::protobuf::reflect::accessor::make_option_accessor::<_, ::protobuf::types::ProtobufTypeUint64>(
+                    "incarnation",
+                    Member::get_incarnation_for_reflect,
+                    Member::mut_incarnation_for_reflect,
+                ));
+                fields.push(::protobuf::reflect::accessor::make_option_accessor::<_, ::protobuf::types::ProtobufTypeBool>(
+                    "left",
+                    Member::get_left_for_reflect,
+                    Member::mut_left_for_reflect,
+                ));
+                fields.push(::protobuf::reflect::accessor::make_singular_field_accessor::<_, ::protobuf::types::ProtobufTypeBytes>(
+                    "ipv6",
+                    Member::get_ipv6_for_reflect,
+                    Member::mut_ipv6_for_reflect,
+                ));
+                fields.push(::protobuf::reflect::accessor::make_singular_field_accessor::<_, ::protobuf::types::ProtobufTypeString>(
+                    "node_id",
+                    Member::get_node_id_for_reflect,
+                    Member::mut_node_id_for_reflect,
+                ));
                 ::protobuf::reflect::MessageDescriptor::new::<Member>(
                     "Member",
                     fields,
@@ -331,6 +541,10 @@ impl ::protobuf::Clear for Member {
         self.clear_port();
         self.clear_suspicion();
         self.clear_heartbeat();
+        self.clear_incarnation();
+        self.clear_left();
+        self.clear_ipv6();
+        self.clear_node_id();
         self.unknown_fields.clear();
     }
 }
@@ -353,6 +567,13 @@ pub struct Gossip {
     heartbeat: ::std::option::Option<u64>,
     kind: ::std::option::Option<u32>,
     members: ::protobuf::RepeatedField<Member>,
+    incarnation: ::std::option::Option<u64>,
+    version: ::std::option::Option<u32>,
+    capabilities: ::std::option::Option<u64>,
+    node_id: ::protobuf::SingularField<::std::string::String>,
+    advertise_addr: ::protobuf::SingularField<::std::string::String>,
+    sender: ::protobuf::SingularPtrField<Member>,
+    target: ::protobuf::SingularPtrField<Member>,
     // special fields
     unknown_fields: ::protobuf::UnknownFields,
     cached_size: ::protobuf::CachedSize,
@@ -462,6 +683,257 @@ impl Gossip {
     fn mut_members_for_reflect(&mut self) -> &mut ::protobuf::RepeatedField<Member> {
         &mut self.members
     }
+
+    // optional uint64 incarnation = 4;
+
+    pub fn clear_incarnation(&mut self) {
+        self.incarnation = ::std::option::Option::None;
+    }
+
+    pub fn has_incarnation(&self) -> bool {
+        self.incarnation.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_incarnation(&mut self, v: u64) {
+        self.incarnation = ::std::option::Option::Some(v);
+    }
+
+    pub fn get_incarnation(&self) -> u64 {
+        self.incarnation.unwrap_or(0)
+    }
+
+    fn get_incarnation_for_reflect(&self) -> &::std::option::Option<u64> {
+        &self.incarnation
+    }
+
+    fn mut_incarnation_for_reflect(&mut self) -> &mut ::std::option::Option<u64> {
+        &mut self.incarnation
+    }
+
+    // optional uint32 version = 5;
+
+    pub fn clear_version(&mut self) {
+        self.version = ::std::option::Option::None;
+    }
+
+    pub fn has_version(&self) -> bool {
+        self.version.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_version(&mut self, v: u32) {
+        self.version = ::std::option::Option::Some(v);
+    }
+
+    pub fn get_version(&self) -> u32 {
+        self.version.unwrap_or(0)
+    }
+
+    fn get_version_for_reflect(&self) -> &::std::option::Option<u32> {
+        &self.version
+    }
+
+    fn mut_version_for_reflect(&mut self) -> &mut ::std::option::Option<u32> {
+        &mut self.version
+    }
+
+    // optional uint64 capabilities = 6;
+
+    pub fn clear_capabilities(&mut self) {
+        self.capabilities = ::std::option::Option::None;
+    }
+
+    pub fn has_capabilities(&self) -> bool {
+        self.capabilities.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_capabilities(&mut self, v: u64) {
+        self.capabilities = ::std::option::Option::Some(v);
+    }
+
+    pub fn get_capabilities(&self) -> u64 {
+        self.capabilities.unwrap_or(0)
+    }
+
+    fn get_capabilities_for_reflect(&self) -> &::std::option::Option<u64> {
+        &self.capabilities
+    }
+
+    fn mut_capabilities_for_reflect(&mut self) -> &mut ::std::option::Option<u64> {
+        &mut self.capabilities
+    }
+
+    // optional string node_id = 7;
+
+    pub fn clear_node_id(&mut self) {
+        self.node_id.clear();
+    }
+
+    pub fn has_node_id(&self) -> bool {
+        self.node_id.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_node_id(&mut self, v: ::std::string::String) {
+        self.node_id = ::protobuf::SingularField::some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_node_id(&mut self) -> &mut ::std::string::String {
+        if self.node_id.is_none() {
+            self.node_id.set_default();
+        }
+        self.node_id.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_node_id(&mut self) -> ::std::string::String {
+        self.node_id.take().unwrap_or_else(|| ::std::string::String::new())
+    }
+
+    pub fn get_node_id(&self) -> &str {
+        match self.node_id.as_ref() {
+            Some(v) => &v,
+            None => "",
+        }
+    }
+
+    fn get_node_id_for_reflect(&self) -> &::protobuf::SingularField<::std::string::String> {
+        &self.node_id
+    }
+
+    fn mut_node_id_for_reflect(&mut self) -> &mut ::protobuf::SingularField<::std::string::String> {
+        &mut self.node_id
+    }
+
+    // optional string advertise_addr = 8;
+
+    pub fn clear_advertise_addr(&mut self) {
+        self.advertise_addr.clear();
+    }
+
+    pub fn has_advertise_addr(&self) -> bool {
+        self.advertise_addr.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_advertise_addr(&mut self, v: ::std::string::String) {
+        self.advertise_addr = ::protobuf::SingularField::some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_advertise_addr(&mut self) -> &mut ::std::string::String {
+        if self.advertise_addr.is_none() {
+            self.advertise_addr.set_default();
+        }
+        self.advertise_addr.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_advertise_addr(&mut self) -> ::std::string::String {
+        self.advertise_addr.take().unwrap_or_else(|| ::std::string::String::new())
+    }
+
+    pub fn get_advertise_addr(&self) -> &str {
+        match self.advertise_addr.as_ref() {
+            Some(v) => &v,
+            None => "",
+        }
+    }
+
+    fn get_advertise_addr_for_reflect(&self) -> &::protobuf::SingularField<::std::string::String> {
+        &self.advertise_addr
+    }
+
+    fn mut_advertise_addr_for_reflect(&mut self) -> &mut ::protobuf::SingularField<::std::string::String> {
+        &mut self.advertise_addr
+    }
+
+    // optional .Member sender = 9;
+
+    pub fn clear_sender(&mut self) {
+        self.sender.clear();
+    }
+
+    pub fn has_sender(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_sender(&mut self, v: Member) {
+        self.sender = ::protobuf::SingularPtrField::some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_sender(&mut self) -> &mut Member {
+        if self.sender.is_none() {
+            self.sender.set_default();
+        }
+        self.sender.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_sender(&mut self) -> Member {
+        self.sender.take().unwrap_or_else(|| Member::new())
+    }
+
+    pub fn get_sender(&self) -> &Member {
+        self.sender.as_ref().unwrap_or_else(|| Member::default_instance())
+    }
+
+    fn get_sender_for_reflect(&self) -> &::protobuf::SingularPtrField<Member> {
+        &self.sender
+    }
+
+    fn mut_sender_for_reflect(&mut self) -> &mut ::protobuf::SingularPtrField<Member> {
+        &mut self.sender
+    }
+
+    // optional .Member target = 10;
+
+    pub fn clear_target(&mut self) {
+        self.target.clear();
+    }
+
+    pub fn has_target(&self) -> bool {
+        self.target.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_target(&mut self, v: Member) {
+        self.target = ::protobuf::SingularPtrField::some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_target(&mut self) -> &mut Member {
+        if self.target.is_none() {
+            self.target.set_default();
+        }
+        self.target.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_target(&mut self) -> Member {
+        self.target.take().unwrap_or_else(|| Member::new())
+    }
+
+    pub fn get_target(&self) -> &Member {
+        self.target.as_ref().unwrap_or_else(|| Member::default_instance())
+    }
+
+    fn get_target_for_reflect(&self) -> &::protobuf::SingularPtrField<Member> {
+        &self.target
+    }
+
+    fn mut_target_for_reflect(&mut self) -> &mut ::protobuf::SingularPtrField<Member> {
+        &mut self.target
+    }
 }
 
 impl ::protobuf::Message for Gossip {
@@ -477,6 +949,16 @@ impl ::protobuf::Message for Gossip {
                 return false;
             }
         };
+        for v in &self.sender {
+            if !v.is_initialized() {
+                return false;
+            }
+        };
+        for v in &self.target {
+            if !v.is_initialized() {
+                return false;
+            }
+        };
         true
     }
 
@@ -501,6 +983,39 @@ impl ::protobuf::Message for Gossip {
                 3 => {
                     ::protobuf::rt::read_repeated_message_into(wire_type, is, &mut self.members)?;
                 },
+                4 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint64()?;
+                    self.incarnation = ::std::option::Option::Some(tmp);
+                },
+                5 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.version = ::std::option::Option::Some(tmp);
+                },
+                6 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint64()?;
+                    self.capabilities = ::std::option::Option::Some(tmp);
+                },
+                7 => {
+                    ::protobuf::rt::read_singular_string_into(wire_type, is, &mut self.node_id)?;
+                },
+                8 => {
+                    ::protobuf::rt::read_singular_string_into(wire_type, is, &mut self.advertise_addr)?;
+                },
+                9 => {
+                    ::protobuf::rt::read_singular_message_into(wire_type, is, &mut self.sender)?;
+                },
+                10 => {
+                    ::protobuf::rt::read_singular_message_into(wire_type, is, &mut self.target)?;
+                },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
                 },
@@ -523,6 +1038,29 @@ impl ::protobuf::Message for Gossip {
             let len = value.compute_size();
             my_size += 1 + ::protobuf::rt::compute_raw_varint32_size(len) + len;
         };
+        if let Some(v) = self.incarnation {
+            my_size += ::protobuf::rt::value_size(4, v, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if let Some(v) = self.version {
+            my_size += ::protobuf::rt::value_size(5, v, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if let Some(v) = self.capabilities {
+            my_size += ::protobuf::rt::value_size(6, v, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if let Some(ref v) = self.node_id.as_ref() {
+            my_size += ::protobuf::rt::string_size(7, &v);
+        }
+        if let Some(ref v) = self.advertise_addr.as_ref() {
+            my_size += ::protobuf::rt::string_size(8, &v);
+        }
+        if let Some(ref v) = self.sender.as_ref() {
+            let len = v.compute_size();
+            my_size += 1 + ::protobuf::rt::compute_raw_varint32_size(len) + len;
+        }
+        if let Some(ref v) = self.target.as_ref() {
+            let len = v.compute_size();
+            my_size += 1 + ::protobuf::rt::compute_raw_varint32_size(len) + len;
+        }
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
@@ -540,6 +1078,31 @@ impl ::protobuf::Message for Gossip {
             os.write_raw_varint32(v.get_cached_size())?;
             v.write_to_with_cached_sizes(os)?;
         };
+        if let Some(v) = self.incarnation {
+            os.write_uint64(4, v)?;
+        }
+        if let Some(v) = self.version {
+            os.write_uint32(5, v)?;
+        }
+        if let Some(v) = self.capabilities {
+            os.write_uint64(6, v)?;
+        }
+        if let Some(ref v) = self.node_id.as_ref() {
+            os.write_string(7, &v)?;
+        }
+        if let Some(ref v) = self.advertise_addr.as_ref() {
+            os.write_string(8, &v)?;
+        }
+        if let Some(ref v) = self.sender.as_ref() {
+            os.write_tag(9, ::protobuf::wire_format::WireTypeLengthDelimited)?;
+            os.write_raw_varint32(v.get_cached_size())?;
+            v.write_to_with_cached_sizes(os)?;
+        }
+        if let Some(ref v) = self.target.as_ref() {
+            os.write_tag(10, ::protobuf::wire_format::WireTypeLengthDelimited)?;
+            os.write_raw_varint32(v.get_cached_size())?;
+            v.write_to_with_cached_sizes(os)?;
+        }
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -599,6 +1162,41 @@ impl ::protobuf::MessageStatic for Gossip {
                     Gossip::get_members_for_reflect,
                     Gossip::mut_members_for_reflect,
                 ));
+                fields.push(::protobuf::reflect::accessor::make_option_accessor::<_, ::protobuf::types::ProtobufTypeUint64>(
+                    "incarnation",
+                    Gossip::get_incarnation_for_reflect,
+                    Gossip::mut_incarnation_for_reflect,
+                ));
+                fields.push(::protobuf::reflect::accessor::make_option_accessor::<_, ::protobuf::types::ProtobufTypeUint32>(
+                    "version",
+                    Gossip::get_version_for_reflect,
+                    Gossip::mut_version_for_reflect,
+                ));
+                fields.push(::protobuf::reflect::accessor::make_option_accessor::<_, ::protobuf::types::ProtobufTypeUint64>(
+                    "capabilities",
+                    Gossip::get_capabilities_for_reflect,
+                    Gossip::mut_capabilities_for_reflect,
+                ));
+                fields.push(::protobuf::reflect::accessor::make_singular_field_accessor::<_, ::protobuf::types::ProtobufTypeString>(
+                    "node_id",
+                    Gossip::get_node_id_for_reflect,
+                    Gossip::mut_node_id_for_reflect,
+                ));
+                fields.push(::protobuf::reflect::accessor::make_singular_field_accessor::<_, ::protobuf::types::ProtobufTypeString>(
+                    "advertise_addr",
+                    Gossip::get_advertise_addr_for_reflect,
+                    Gossip::mut_advertise_addr_for_reflect,
+                ));
+                fields.push(::protobuf::reflect::accessor::make_singular_ptr_field_accessor::<_, ::protobuf::types::ProtobufTypeMessage<Member>>(
+                    "sender",
+                    Gossip::get_sender_for_reflect,
+                    Gossip::mut_sender_for_reflect,
+                ));
+                fields.push(::protobuf::reflect::accessor::make_singular_ptr_field_accessor::<_, ::protobuf::types::ProtobufTypeMessage<Member>>(
+                    "target",
+                    Gossip::get_target_for_reflect,
+                    Gossip::mut_target_for_reflect,
+                ));
                 ::protobuf::reflect::MessageDescriptor::new::<Gossip>(
                     "Gossip",
                     fields,
@@ -614,6 +1212,13 @@ impl ::protobuf::Clear for Gossip {
         self.clear_heartbeat();
         self.clear_kind();
         self.clear_members();
+        self.clear_incarnation();
+        self.clear_version();
+        self.clear_capabilities();
+        self.clear_node_id();
+        self.clear_advertise_addr();
+        self.clear_sender();
+        self.clear_target();
         self.unknown_fields.clear();
     }
 }
@@ -631,11 +1236,18 @@ impl ::protobuf::reflect::ProtobufValue for Gossip {
 }
 
 static file_descriptor_proto_data: &'static [u8] = b"\
-    \n\tmsg.proto\"H\n\x06Member\x12\n\n\x02ip\x18\x01\x20\x02(\r\x12\x0c\n\
-    \x04port\x18\x02\x20\x02(\r\x12\x11\n\tsuspicion\x18\x03\x20\x02(\x01\
-    \x12\x11\n\theartbeat\x18\x04\x20\x02(\x04\"C\n\x06Gossip\x12\x11\n\thea\
-    rtbeat\x18\x01\x20\x02(\x04\x12\x0c\n\x04kind\x18\x02\x20\x02(\r\x12\x18\
-    \n\x07members\x18\x03\x20\x03(\x0b2\x07.Member\
+    \n\tmsg.proto\"\x8a\x01\n\x06Member\x12\n\n\x02ip\x18\x01\x20\x02(\r\x12\
+    \x0c\n\x04port\x18\x02\x20\x02(\r\x12\x11\n\tsuspicion\x18\x03\x20\x02(\
+    \x01\x12\x11\n\theartbeat\x18\x04\x20\x02(\x04\x12\x13\n\x0bincarnation\
+    \x18\x05\x20\x01(\x04\x12\x0c\n\x04left\x18\x06\x20\x01(\x08\x12\x0c\n\
+    \x04ipv6\x18\x07\x20\x01(\x0c\x12\x0f\n\x07node_id\x18\x08\x20\x01(\t\"\
+    \xda\x01\n\x06Gossip\x12\x11\n\theartbeat\x18\x01\x20\x02(\x04\x12\x0c\n\
+    \x04kind\x18\x02\x20\x02(\r\x12\x18\n\x07members\x18\x03\x20\x03(\x0b2\
+    \x07.Member\x12\x13\n\x0bincarnation\x18\x04\x20\x01(\x04\x12\x0f\n\x07v\
+    ersion\x18\x05\x20\x01(\r\x12\x14\n\x0ccapabilities\x18\x06\x20\x01(\x04\
+    \x12\x0f\n\x07node_id\x18\x07\x20\x01(\t\x12\x16\n\x0eadvertise_addr\x18\
+    \x08\x20\x01(\t\x12\x17\n\x06sender\x18\t\x20\x01(\x0b2\x07.Member\x12\
+    \x17\n\x06target\x18\n\x20\x01(\x0b2\x07.Member\
 ";
 
 static mut file_descriptor_proto_lazy: ::protobuf::lazy::Lazy<::protobuf::descriptor::FileDescriptorProto> = ::protobuf::lazy::Lazy {
@@ -1,11 +1,35 @@
-use proto::msg::{Member, Gossip};
-use std::net::{SocketAddr, AddrParseError, IpAddr, Ipv4Addr, ToSocketAddrs};
+use crate::proto::msg::{Member, Gossip};
+use std::net::{SocketAddr, AddrParseError, IpAddr, Ipv4Addr, Ipv6Addr, ToSocketAddrs};
 use std::io;
+use std::time::Duration;
+use rand::{thread_rng, Rng};
 
-#[derive(Clone, Debug)]
+/// The version of the wire protocol we speak, sent along with every gossip.
+/// Peers that predate versioning send none, which reads as 0.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// We understand Leave gossip.
+pub const CAP_LEAVE: u64 = 1 << 0;
+
+/// We understand PingReq and IndirectAck gossip.
+pub const CAP_PING_REQ: u64 = 1 << 1;
+
+/// We gossip our phi for every member in `Member.suspicion`.
+pub const CAP_SUSPICION: u64 = 1 << 2;
+
+/// The optional protocol features we support, sent along with every gossip.
+pub const CAPABILITIES: u64 = CAP_LEAVE | CAP_PING_REQ | CAP_SUSPICION;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum GossipType {
     Syn,
     Ack,
+    /// Sent by a node that is shutting down on purpose.
+    Leave,
+    /// Asks the receiver to probe `Gossip.target` on the sender's behalf.
+    PingReq,
+    /// Tells whoever sent us a PingReq that its target answered our probe.
+    IndirectAck,
 }
 
 impl GossipType {
@@ -14,6 +38,12 @@ impl GossipType {
             Some(GossipType::Syn)
         } else if v == 1 {
             Some(GossipType::Ack)
+        } else if v == 2 {
+            Some(GossipType::Leave)
+        } else if v == 3 {
+            Some(GossipType::PingReq)
+        } else if v == 4 {
+            Some(GossipType::IndirectAck)
         } else {
             None
         }
@@ -25,43 +55,53 @@ impl Into<u32> for GossipType {
         match self.clone() {
             GossipType::Ack => 1,
             GossipType::Syn => 0,
+            GossipType::Leave => 2,
+            GossipType::PingReq => 3,
+            GossipType::IndirectAck => 4,
         }
     }
 }
 
 
-pub fn ip_number_and_port_from_sockaddr(addr: SocketAddr) -> Result<(u32, u16), AddrParseError> {
-    if let SocketAddr::V4(addr) = addr {
-        let octets = addr.ip().octets();
-        let ip = ((octets[0] as u32) << 24) | ((octets[1] as u32) << 16) |
-            ((octets[2] as u32) << 8) | octets[3] as u32;
-        Ok((ip, addr.port()))
-    } else {
-        "invalidaddr".parse::<SocketAddr>().map(|_| (0, 0))
-    }
-}
-
 pub fn member_from_address(addr: &str) -> Result<Member, AddrParseError> {
     let addr = addr.parse::<SocketAddr>()?;
-    member_from_sockaddr(addr)
+    Ok(member_from_sockaddr(addr))
 }
 
-pub fn member_from_sockaddr(addr: SocketAddr) -> Result<Member, AddrParseError> {
+pub fn member_from_sockaddr(addr: SocketAddr) -> Member {
     let mut member = Member::new();
-    let (ip, port) = ip_number_and_port_from_sockaddr(addr)?;
-    member.set_ip(ip);
-    member.set_port(port as u32);
+    set_member_addr(&mut member, addr);
     member.set_heartbeat(0);
     member.set_suspicion(0f64);
-    Ok(member)
+    member
 }
 
-pub fn make_gossip<I>(heartbeat: u64, members: I, typ: GossipType) -> Gossip
+/// Point `member` at `addr`, see `member_addr`.
+pub fn set_member_addr(member: &mut Member, addr: SocketAddr) {
+    let addr = canonical_addr(addr);
+    match addr {
+        SocketAddr::V4(v4) => {
+            member.set_ip(u32::from(*v4.ip()));
+            member.clear_ipv6();
+        }
+        SocketAddr::V6(v6) => {
+            // ip is required, so peers that don't know of ipv6 see 0.0.0.0.
+            member.set_ip(0);
+            member.set_ipv6(v6.ip().octets().to_vec());
+        }
+    }
+    member.set_port(addr.port() as u32);
+}
+
+pub fn make_gossip<I>(incarnation: u64, heartbeat: u64, members: I, typ: GossipType) -> Gossip
 where
     I: Iterator<Item = Member>,
 {
     let mut gossip = Gossip::new();
     gossip.set_kind(typ.into());
+    gossip.set_version(PROTOCOL_VERSION);
+    gossip.set_capabilities(CAPABILITIES);
+    gossip.set_incarnation(incarnation);
     gossip.set_heartbeat(heartbeat);
     for member in members {
         gossip.mut_members().push(member.clone());
@@ -70,24 +110,115 @@ where
 }
 
 pub fn member_addr(member: &Member) -> SocketAddr {
+    let port = member.get_port() as u16;
+    let ipv6 = member.get_ipv6();
+    if ipv6.len() == 16 {
+        let mut octets = [0u8; 16];
+        octets.copy_from_slice(ipv6);
+        return SocketAddr::new(IpAddr::V6(Ipv6Addr::from(octets)), port);
+    }
+
     let ip = member.get_ip();
     let a = ((ip >> 24) & 0xff) as u8;
     let b = ((ip >> 16) & 0xff) as u8;
     let c = ((ip >> 8) & 0xff) as u8;
     let d = (ip & 0xff) as u8;
     let ipaddr = IpAddr::V4(Ipv4Addr::new(a, b, c, d));
-    let port = member.get_port() as u16;
     SocketAddr::new(ipaddr, port)
 }
 
+/// Whether `addr` is one peers can make sense of, which `0.0.0.0` or port 0
+/// aren't.
+pub fn is_reachable_addr(addr: SocketAddr) -> bool {
+    !addr.ip().is_unspecified() && addr.port() != 0
+}
+
+/// The sender of `gossip` as a member. Peers send their own record along,
+/// except for older ones, for which we piece it together from the gossip. If
+/// the record doesn't say where to reach the sender, we go by `sender_addr`.
+pub fn sender_member(from_addr: SocketAddr, gossip: &Gossip) -> Member {
+    let mut sender = if gossip.has_sender() {
+        gossip.get_sender().clone()
+    } else {
+        let mut sender = Member::new();
+        sender.set_heartbeat(gossip.get_heartbeat());
+        sender.set_incarnation(gossip.get_incarnation());
+        sender.set_suspicion(0f64);
+        if gossip.has_node_id() {
+            sender.set_node_id(gossip.get_node_id().to_string());
+        }
+        sender
+    };
+    if !is_reachable_addr(member_addr(&sender)) {
+        set_member_addr(&mut sender, sender_addr(from_addr, gossip));
+    }
+    sender
+}
+
+/// The address to reach the sender of `gossip` at: the one it advertises if
+/// any, or else the one it sent `gossip` from.
+pub fn sender_addr(from_addr: SocketAddr, gossip: &Gossip) -> SocketAddr {
+    if gossip.has_advertise_addr() {
+        match gossip.get_advertise_addr().parse::<SocketAddr>() {
+            Ok(addr) => return addr,
+            Err(_) => warn!(
+                "{:?} advertises a bad address {:?}",
+                &from_addr,
+                gossip.get_advertise_addr()
+            ),
+        }
+    }
+    from_addr
+}
 
+/// Peers reaching a dual-stack IPv6 socket over IPv4 show up with
+/// IPv4-mapped IPv6 addresses. We always go by the plain IPv4 address, so
+/// that a member is the same member whichever way we hear from it.
+pub fn canonical_addr(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V6(v6) => match v6.ip().to_ipv4_mapped() {
+            Some(ip) => SocketAddr::new(IpAddr::V4(ip), v6.port()),
+            None => addr,
+        },
+        SocketAddr::V4(_) => addr,
+    }
+}
+
+/// `addr` in a form that a socket bound to `local` can send to, which for
+/// IPv4 peers and an IPv6 socket means IPv4-mapped.
+pub fn sendable_addr(addr: SocketAddr, local: SocketAddr) -> SocketAddr {
+    match (addr, local) {
+        (SocketAddr::V4(v4), SocketAddr::V6(_)) => {
+            SocketAddr::new(IpAddr::V6(v4.ip().to_ipv6_mapped()), v4.port())
+        }
+        _ => addr,
+    }
+}
+
+/// An incarnation number for a process starting now: the current time in
+/// milliseconds since the epoch.
+pub fn startup_incarnation() -> u64 {
+    let now = time::get_time();
+    now.sec as u64 * 1000 + now.nsec as u64 / 1_000_000
+}
+
+/// A random node ID, for nodes that aren't given one.
+pub fn generate_node_id() -> String {
+    let mut rng = thread_rng();
+    format!("{:016x}{:016x}", rng.gen::<u64>(), rng.gen::<u64>())
+}
+
+/// A random delay of up to `max_secs` seconds, see `Config::ticker_delay`.
+pub fn random_delay(max_secs: u8) -> Duration {
+    Duration::from_millis(thread_rng().gen_range(0, max_secs as u64 * 1000 + 1))
+}
+
+pub fn duration_secs(d: Duration) -> f64 {
+    d.as_secs() as f64 + d.subsec_nanos() as f64 * 1e-9
+}
 
 
-pub fn resolve_first_ipv4(host: &str) -> io::Result<Option<SocketAddr>> {
-    Ok(
-        host.to_socket_addrs()? // Err return happens here
-        .filter(SocketAddr::is_ipv4)
-        .take(1)
-        .next(),
-    )
+/// The first address `host` resolves to, be it IPv4 or IPv6.
+pub fn resolve_first(host: &str) -> io::Result<Option<SocketAddr>> {
+    Ok(host.to_socket_addrs()?.next())
 }
@@ -25,10 +25,9 @@ for my $who ( sort @logfiles ) {
     my $sq_sum = 0;
     my $total = 0;
     for my $line ( @lines ) {
-        if ( $line =~ /phi\((.*)\)=([^\s]+)\s*$/ ) {
+        # The phi may be followed by the status of the peer, e.g. "(Alive)".
+        if ( $line =~ /phi\(([^()\s]+)\)=([^\s]+)(?:\s+\(\w+\))?\s*$/ ) {
             my ( $peer, $phi ) = ( $1, $2 );
-            my ( $peer_ip_num, $peer_port ) = split /:/, $peer;
-            my $ip = ip_number_to_ip_addr( $peer_ip_num );
             $min = $phi < $min ? $phi : $min;
             $max = $phi > $max ? $phi : $max;
             $sum += $phi;
@@ -36,14 +35,11 @@ for my $who ( sort @logfiles ) {
             ++$total;
         }
     }
+    if ( !$total ) {
+        say "no phi values found";
+        next;
+    }
     my $mean = $sum / $total;
     my $var = $sq_sum / $total - $mean * $mean;
     say "max: $max, min: $min, mean: $mean, var: $var";
 }
-
-
-sub ip_number_to_ip_addr {
-    my $n = shift;
-    return sprintf "%d.%d.%d.%d",
-        (($n >> 24) & 0xff), (($n >> 16) & 0xff), (($n >> 8) & 0xff), ($n & 0xff);
-}
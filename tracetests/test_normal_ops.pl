@@ -198,19 +198,15 @@ essentially making a 2D table of stats:
         my $our_key;
         for my $line (@lines) {
             if ( !defined $our_key ) {
-                my ( $our_addr, $our_port ) = $line =~
-                  /starting failure detector now on (\d+\.\d+\.\d+\.\d+):(\d+)/;
-                $our_key = "$our_addr:$our_port"
-                  if defined $our_addr and defined $our_port;
+                ($our_key) =
+                  $line =~ /starting failure detector now on (\S+)/;
                 next;
             }
 
-            my ( $host, $port, $phi ) =
-              $line =~ /phi\((\d+):(\d+)\)=\s*([^\s]+)/;
-            next unless defined $host and defined $port and defined $phi;
-
-            my $ip  = ip_number_to_dotted($host);
-            my $key = "$ip:$port";
+            # Peers are logged by their member ID, which is either their
+            # address or their node ID.
+            my ( $key, $phi ) = $line =~ /phi\(([^()\s]+)\)=\s*([^\s]+)/;
+            next unless defined $key and defined $phi;
 
             $phi = lc $phi;    # in case it is Inf.
             die "funny value for phi matched: $phi"
@@ -296,16 +292,6 @@ sub check_one {
     return %errors;
 }
 
-sub ip_number_to_dotted {
-    my $num = shift;
-    return sprintf '%d.%d.%d.%d',
-      ( $num >> 24 ) & 0xff,
-      ( $num >> 16 ) & 0xff,
-      ( $num >> 8 ) & 0xff,
-      $num & 0xff,
-      ;
-}
-
 do {
 
     my %opts;